@@ -1,4 +1,12 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Clone)]
 pub enum CellValue {
+    Null,
     Int(i64),
     Float(f64),
     Text(String),
@@ -8,10 +16,163 @@ pub enum CellValue {
 impl CellValue {
     pub fn as_string(&self) -> String {
         match self {
+            CellValue::Null => String::new(),
             CellValue::Int(i) => i.to_string(),
             CellValue::Float(f) => f.to_string(),
             CellValue::Text(s) => s.clone(),
             CellValue::DateTime(dt) => dt.to_string(),
         }
     }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            CellValue::Null => "null",
+            CellValue::Int(_) => "int",
+            CellValue::Float(_) => "float",
+            CellValue::Text(_) => "text",
+            CellValue::DateTime(_) => "datetime",
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, CellValue::Null)
+    }
+
+    /// Numeric view of the value. Text is parsed, nulls and unparsable text yield `None`.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            CellValue::Null => None,
+            CellValue::Int(i) | CellValue::DateTime(i) => Some(*i as f64),
+            CellValue::Float(f) => Some(*f),
+            CellValue::Text(s) => s.parse().ok(),
+        }
+    }
+
+    /// SQL-style comparison: nulls and values of unrelated types are not
+    /// comparable, ints and floats compare numerically.
+    pub fn compare(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (CellValue::Int(a), CellValue::Float(b)) => (*a as f64).partial_cmp(b),
+            (CellValue::Float(a), CellValue::Int(b)) => a.partial_cmp(&(*b as f64)),
+            (CellValue::Float(a), CellValue::Float(b)) => a.partial_cmp(b),
+            (CellValue::Int(_), CellValue::Int(_))
+            | (CellValue::Text(_), CellValue::Text(_))
+            | (CellValue::DateTime(_), CellValue::DateTime(_)) => Some(self.cmp(other)),
+            _ => None,
+        }
+    }
+
+    // Position of each variant when comparing values of different types
+    fn rank(&self) -> u8 {
+        match self {
+            CellValue::Null => 0,
+            CellValue::Int(_) => 1,
+            CellValue::Float(_) => 2,
+            CellValue::DateTime(_) => 3,
+            CellValue::Text(_) => 4,
+        }
+    }
+}
+
+// Floats are compared by their total order so that values can be used as hash
+// and sort keys (e.g. group and join keys). Nulls sort first.
+impl PartialEq for CellValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for CellValue {}
+
+impl PartialOrd for CellValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CellValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (CellValue::Int(a), CellValue::Int(b)) => a.cmp(b),
+            (CellValue::Float(a), CellValue::Float(b)) => a.total_cmp(b),
+            (CellValue::Text(a), CellValue::Text(b)) => a.cmp(b),
+            (CellValue::DateTime(a), CellValue::DateTime(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl Hash for CellValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.rank().hash(state);
+        match self {
+            CellValue::Null => {}
+            CellValue::Int(i) | CellValue::DateTime(i) => i.hash(state),
+            CellValue::Float(f) => f.to_bits().hash(state),
+            CellValue::Text(s) => s.hash(state),
+        }
+    }
+}
+
+// Cells serialize as plain values: null, numbers (datetimes as timestamps) or strings
+impl Serialize for CellValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            CellValue::Null => serializer.serialize_none(),
+            CellValue::Int(i) | CellValue::DateTime(i) => serializer.serialize_i64(*i),
+            CellValue::Float(f) => serializer.serialize_f64(*f),
+            CellValue::Text(s) => serializer.serialize_str(s),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CellValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(CellValueVisitor)
+    }
+}
+
+struct CellValueVisitor;
+
+impl<'de> Visitor<'de> for CellValueVisitor {
+    type Value = CellValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("null, a number or a string")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<CellValue, E> {
+        Ok(CellValue::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<CellValue, E> {
+        Ok(CellValue::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<CellValue, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<CellValue, E> {
+        Ok(CellValue::Int(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<CellValue, E> {
+        match i64::try_from(v) {
+            Ok(i) => Ok(CellValue::Int(i)),
+            Err(_) => Ok(CellValue::Float(v as f64)),
+        }
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<CellValue, E> {
+        Ok(CellValue::Float(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<CellValue, E> {
+        Ok(CellValue::Text(v.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<CellValue, E> {
+        Ok(CellValue::Text(v))
+    }
 }
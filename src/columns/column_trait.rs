@@ -1,14 +1,134 @@
+use serde::{Deserialize, Serialize};
+use crate::cell_value::CellValue;
+use crate::columns::datetime_column::DateTimeColumn;
+use crate::columns::float_column::FloatColumn;
+use crate::columns::int_column::IntColumn;
+use crate::columns::string_column::StringColumn;
+use crate::error::DmError;
+
 pub trait ColumnTrait {
     fn name(&self) -> &str;
     fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn get(&self, idx: usize) -> CellValue;
     fn get_as_string(&self, idx: usize) -> String;
-    fn push_str(&mut self, val: &str);
+    fn try_push(&mut self, val: CellValue) -> Result<(), DmError>;
+    fn push(&mut self, val: CellValue) {
+        if let Err(e) = self.try_push(val) {
+            panic!("{}", e);
+        }
+    }
+    fn try_push_str(&mut self, val: &str) -> Result<(), DmError>;
+    fn push_str(&mut self, val: &str) {
+        if let Err(e) = self.try_push_str(val) {
+            panic!("{}", e);
+        }
+    }
+    fn is_null(&self, idx: usize) -> bool;
+    fn push_null(&mut self);
+    fn null_count(&self) -> usize {
+        (0..self.len()).filter(|&i| self.is_null(i)).count()
+    }
+    /// Shorten the column to `len` values, used to roll back partially pushed rows.
+    fn truncate(&mut self, len: usize);
     fn clone_box(&self) -> Box<dyn ColumnTrait>;
     fn get_type(&self) -> ColumnType;
+    /// Multi-level header of a column that comes from a pivot. Columns without
+    /// one are printed with their name alone.
+    fn header(&self) -> Option<&ColumnHeader> {
+        None
+    }
+    /// Attach or clear the header. The default does nothing, for columns that
+    /// never carry one.
+    fn set_header(&mut self, _header: Option<ColumnHeader>) {}
+}
+
+/// The parts a pivoted column is made of, shown as stacked header lines when
+/// the table is printed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnHeader {
+    /// The aggregated source column
+    pub value_column: String,
+    /// The aggregation, e.g. `Sum`
+    pub aggregation: String,
+    /// One value per column group, e.g. `[Text("North"), Int(2024)]`
+    pub key: Vec<CellValue>,
 }
 
+impl ColumnHeader {
+    /// Header lines from top to bottom: the value column, the aggregation,
+    /// then each key part.
+    pub fn levels(&self) -> Vec<String> {
+        let mut levels = vec![self.value_column.clone(), self.aggregation.clone()];
+        levels.extend(self.key.iter().map(CellValue::as_string));
+        levels
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ColumnType {
     String,
     Int,
     Float,
+    DateTime,
+}
+
+impl ColumnType {
+    /// Create an empty column of this type.
+    pub fn new_column(self, name: &str) -> Box<dyn ColumnTrait> {
+        match self {
+            ColumnType::String => Box::new(StringColumn::new(name)),
+            ColumnType::Int => Box::new(IntColumn::new(name)),
+            ColumnType::Float => Box::new(FloatColumn::new(name)),
+            ColumnType::DateTime => Box::new(DateTimeColumn::new(name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [ColumnType; 4] = [ColumnType::String, ColumnType::Int, ColumnType::Float, ColumnType::DateTime];
+
+    #[test]
+    fn nulls_are_tracked_per_cell() {
+        for col_type in ALL {
+            let mut col = col_type.new_column("c");
+            col.push_str("1");
+            col.push_null();
+            col.push_str("2");
+            assert_eq!(col.len(), 3);
+            assert_eq!((0..3).map(|i| col.is_null(i)).collect::<Vec<_>>(), [false, true, false], "{:?}", col_type);
+            assert_eq!(col.get(1), CellValue::Null);
+            assert_eq!(col.null_count(), 1);
+        }
+    }
+
+    #[test]
+    fn empty_text_is_null_except_for_strings() {
+        for col_type in ALL {
+            let mut col = col_type.new_column("c");
+            col.push_str("");
+            assert_eq!(col.is_null(0), col_type != ColumnType::String, "{:?}", col_type);
+        }
+    }
+
+    #[test]
+    fn truncate_and_clone_keep_validity() {
+        for col_type in ALL {
+            let mut col = col_type.new_column("c");
+            col.push(CellValue::Null);
+            col.push_str("3");
+            col.push_null();
+            col.truncate(2);
+            let copy = col.clone_box();
+            assert_eq!(copy.len(), 2);
+            assert!(copy.is_null(0) && !copy.is_null(1));
+            assert_eq!(copy.name(), "c");
+            assert_eq!(copy.get_type(), col_type);
+        }
+    }
 }
@@ -1,10 +1,15 @@
 use crate::columns::column_trait::ColumnTrait;
 use crate::columns::column_trait::ColumnType;
+use crate::columns::column_trait::ColumnHeader;
+use crate::cell_value::CellValue;
+use crate::error::DmError;
 
 #[derive(Clone)]
 pub struct DateTimeColumn {
     name: String,
     data: Vec<i64>, // UNIX timestamps
+    validity: Vec<bool>,
+    header: Option<ColumnHeader>,
 }
 
 impl DateTimeColumn {
@@ -12,14 +17,24 @@ impl DateTimeColumn {
         Self {
             name: name.to_string(),
             data: Vec::new(),
+            validity: Vec::new(),
+            header: None,
         }
     }
     pub fn push(&mut self, val: i64) {
         self.data.push(val);
+        self.validity.push(true);
     }
     pub fn get(&self, idx: usize) -> i64 {
         self.data[idx]
     }
+    pub fn get_opt(&self, idx: usize) -> Option<i64> {
+        if self.validity[idx] {
+            Some(self.data[idx])
+        } else {
+            None
+        }
+    }
 }
 
 impl ColumnTrait for DateTimeColumn {
@@ -29,17 +44,179 @@ impl ColumnTrait for DateTimeColumn {
     fn len(&self) -> usize {
         self.data.len()
     }
+    fn get(&self, idx: usize) -> CellValue {
+        match self.get_opt(idx) {
+            Some(v) => CellValue::DateTime(v),
+            None => CellValue::Null,
+        }
+    }
     fn get_as_string(&self, idx: usize) -> String {
-        self.data[idx].to_string()
+        if self.validity[idx] {
+            self.data[idx].to_string()
+        } else {
+            String::new()
+        }
+    }
+    fn try_push(&mut self, val: CellValue) -> Result<(), DmError> {
+        match val {
+            CellValue::Null => self.push_null(),
+            CellValue::DateTime(v) | CellValue::Int(v) => DateTimeColumn::push(self, v),
+            CellValue::Text(s) => return self.try_push_str(&s),
+            other => {
+                return Err(DmError::TypeMismatch {
+                    column: self.name.clone(),
+                    expected: ColumnType::DateTime,
+                    found: other.type_name().to_string(),
+                })
+            }
+        }
+        Ok(())
+    }
+    fn try_push_str(&mut self, val: &str) -> Result<(), DmError> {
+        // Accepts UNIX timestamps and ISO 8601 dates (see `parse_datetime`).
+        // An empty cell has nothing to parse, so it is stored as null
+        if val.is_empty() {
+            self.push_null();
+            return Ok(());
+        }
+        let parsed = parse_datetime(val).ok_or_else(|| DmError::ParseError {
+            row: self.data.len(),
+            column: self.name.clone(),
+            value: val.to_string(),
+        })?;
+        self.push(parsed);
+        Ok(())
+    }
+    fn is_null(&self, idx: usize) -> bool {
+        !self.validity[idx]
     }
-    fn push_str(&mut self, val: &str) {
-        let parsed = val.parse::<i64>().expect("Invalid datetime (timestamp) value");
-        self.data.push(parsed);
+    fn push_null(&mut self) {
+        self.data.push(0);
+        self.validity.push(false);
     }
+    fn truncate(&mut self, len: usize) {
+        self.data.truncate(len);
+        self.validity.truncate(len);
+    }
+
     fn clone_box(&self) -> Box<dyn ColumnTrait> {
         Box::new(self.clone())
     }
     fn get_type(&self) -> ColumnType {
-        ColumnType::Int
+        ColumnType::DateTime
+    }
+    fn header(&self) -> Option<&ColumnHeader> {
+        self.header.as_ref()
+    }
+    fn set_header(&mut self, header: Option<ColumnHeader>) {
+        self.header = header;
+    }
+}
+
+/// Parse a UNIX timestamp (seconds) or an ISO 8601 date / datetime in UTC:
+/// `2024-03-01`, `2024-03-01T12:30:00`, `2024-03-01 12:30:00` (optionally suffixed with `Z`).
+pub fn parse_datetime(val: &str) -> Option<i64> {
+    if let Ok(ts) = val.parse::<i64>() {
+        return Some(ts);
+    }
+    let val = val.strip_suffix('Z').unwrap_or(val);
+    let (date, time) = match val.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (val, None),
+    };
+
+    let date_parts: Vec<&str> = date.split('-').collect();
+    if date_parts.len() != 3 || date_parts[0].len() != 4 || date_parts[1].len() != 2 || date_parts[2].len() != 2 {
+        return None;
+    }
+    let year: i64 = date_parts[0].parse().ok()?;
+    let month: i64 = date_parts[1].parse().ok()?;
+    let day: i64 = date_parts[2].parse().ok()?;
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let mut seconds = 0;
+    if let Some(time) = time {
+        let time_parts: Vec<&str> = time.split(':').collect();
+        if time_parts.len() != 3 || time_parts.iter().any(|p| p.len() != 2) {
+            return None;
+        }
+        let hour: i64 = time_parts[0].parse().ok()?;
+        let minute: i64 = time_parts[1].parse().ok()?;
+        let second: i64 = time_parts[2].parse().ok()?;
+        if hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+        seconds = hour * 3600 + minute * 60 + second;
+    }
+
+    Some(days_from_civil(year, month, day) * 86400 + seconds)
+}
+
+/// Format a UNIX timestamp as an ISO 8601 UTC datetime, e.g. `2024-03-01T12:30:00Z`.
+pub fn format_datetime(ts: i64) -> String {
+    let days = ts.div_euclid(86400);
+    let seconds = ts.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 in the proleptic Gregorian calendar (H. Hinnant's algorithm)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_parses_timestamps_and_iso_dates() {
+        let mut col = DateTimeColumn::new("t");
+        col.try_push_str("2024-03-01").unwrap();
+        col.try_push_str("2024-03-01T12:30:00Z").unwrap();
+        col.try_push(CellValue::Int(0)).unwrap();
+        assert_eq!(ColumnTrait::get(&col, 0), CellValue::DateTime(1_709_251_200));
+        assert_eq!(ColumnTrait::get(&col, 1), CellValue::DateTime(1_709_251_200 + 45_000));
+        assert_eq!(format_datetime(1_709_251_200 + 45_000), "2024-03-01T12:30:00Z");
+
+        assert!(matches!(col.try_push(CellValue::Float(1.0)), Err(DmError::TypeMismatch { .. })));
+        assert!(matches!(col.try_push_str("2024-13-01"), Err(DmError::ParseError { .. })));
     }
 }
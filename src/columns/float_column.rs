@@ -1,10 +1,15 @@
 use crate::columns::column_trait::ColumnTrait;
 use crate::columns::column_trait::ColumnType;
+use crate::columns::column_trait::ColumnHeader;
+use crate::cell_value::CellValue;
+use crate::error::DmError;
 
 #[derive(Clone)]
 pub struct FloatColumn {
     name: String,
     data: Vec<f64>,
+    validity: Vec<bool>,
+    header: Option<ColumnHeader>,
 }
 
 impl FloatColumn {
@@ -12,14 +17,24 @@ impl FloatColumn {
         Self {
             name: name.to_string(),
             data: Vec::new(),
+            validity: Vec::new(),
+            header: None,
         }
     }
     pub fn push(&mut self, val: f64) {
         self.data.push(val);
+        self.validity.push(true);
     }
     pub fn get(&self, idx: usize) -> f64 {
         self.data[idx]
     }
+    pub fn get_opt(&self, idx: usize) -> Option<f64> {
+        if self.validity[idx] {
+            Some(self.data[idx])
+        } else {
+            None
+        }
+    }
 }
 
 impl ColumnTrait for FloatColumn {
@@ -29,17 +44,89 @@ impl ColumnTrait for FloatColumn {
     fn len(&self) -> usize {
         self.data.len()
     }
+    fn get(&self, idx: usize) -> CellValue {
+        match self.get_opt(idx) {
+            Some(v) => CellValue::Float(v),
+            None => CellValue::Null,
+        }
+    }
     fn get_as_string(&self, idx: usize) -> String {
-        self.data[idx].to_string()
+        if self.validity[idx] {
+            self.data[idx].to_string()
+        } else {
+            String::new()
+        }
+    }
+    fn try_push(&mut self, val: CellValue) -> Result<(), DmError> {
+        match val {
+            CellValue::Null => self.push_null(),
+            CellValue::Float(v) => FloatColumn::push(self, v),
+            CellValue::Int(v) => FloatColumn::push(self, v as f64),
+            CellValue::Text(s) => return self.try_push_str(&s),
+            other => {
+                return Err(DmError::TypeMismatch {
+                    column: self.name.clone(),
+                    expected: ColumnType::Float,
+                    found: other.type_name().to_string(),
+                })
+            }
+        }
+        Ok(())
+    }
+    fn try_push_str(&mut self, val: &str) -> Result<(), DmError> {
+        // An empty cell has nothing to parse, so it is stored as null
+        if val.is_empty() {
+            self.push_null();
+            return Ok(());
+        }
+        let parsed = val.parse::<f64>().map_err(|_| DmError::ParseError {
+            row: self.data.len(),
+            column: self.name.clone(),
+            value: val.to_string(),
+        })?;
+        self.push(parsed);
+        Ok(())
+    }
+    fn is_null(&self, idx: usize) -> bool {
+        !self.validity[idx]
     }
-    fn push_str(&mut self, val: &str) {
-        let parsed = val.parse::<f64>().expect("Invalid float");
-        self.data.push(parsed);
+    fn push_null(&mut self) {
+        self.data.push(0.0);
+        self.validity.push(false);
     }
+    fn truncate(&mut self, len: usize) {
+        self.data.truncate(len);
+        self.validity.truncate(len);
+    }
+
     fn clone_box(&self) -> Box<dyn ColumnTrait> {
         Box::new(self.clone())
     }
     fn get_type(&self) -> ColumnType {
         ColumnType::Float
     }
+    fn header(&self) -> Option<&ColumnHeader> {
+        self.header.as_ref()
+    }
+    fn set_header(&mut self, header: Option<ColumnHeader>) {
+        self.header = header;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_converts_or_rejects() {
+        let mut col = FloatColumn::new("x");
+        col.try_push(CellValue::Float(0.5)).unwrap();
+        col.try_push(CellValue::Int(2)).unwrap();
+        col.try_push(CellValue::Text("1e3".into())).unwrap();
+        assert_eq!((0..3).map(|i| ColumnTrait::get(&col, i)).collect::<Vec<_>>(), [CellValue::Float(0.5), CellValue::Float(2.0), CellValue::Float(1000.0)]);
+
+        assert!(matches!(col.try_push(CellValue::DateTime(1)), Err(DmError::TypeMismatch { .. })));
+        assert!(matches!(col.try_push_str("abc"), Err(DmError::ParseError { row: 3, .. })));
+        assert_eq!(col.len(), 3);
+    }
 }
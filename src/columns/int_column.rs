@@ -1,10 +1,15 @@
 use crate::columns::column_trait::ColumnTrait;
 use crate::columns::column_trait::ColumnType;
+use crate::columns::column_trait::ColumnHeader;
+use crate::cell_value::CellValue;
+use crate::error::DmError;
 
 #[derive(Clone)]
 pub struct IntColumn {
     name: String,
     data: Vec<i64>,
+    validity: Vec<bool>,
+    header: Option<ColumnHeader>,
 }
 
 impl IntColumn {
@@ -12,14 +17,24 @@ impl IntColumn {
         Self {
             name: name.to_string(),
             data: Vec::new(),
+            validity: Vec::new(),
+            header: None,
         }
     }
     pub fn push(&mut self, val: i64) {
         self.data.push(val);
+        self.validity.push(true);
     }
     pub fn get(&self, idx: usize) -> i64 {
         self.data[idx]
     }
+    pub fn get_opt(&self, idx: usize) -> Option<i64> {
+        if self.validity[idx] {
+            Some(self.data[idx])
+        } else {
+            None
+        }
+    }
 }
 
 impl ColumnTrait for IntColumn {
@@ -31,13 +46,64 @@ impl ColumnTrait for IntColumn {
         self.data.len()
     }
 
+    fn get(&self, idx: usize) -> CellValue {
+        match self.get_opt(idx) {
+            Some(v) => CellValue::Int(v),
+            None => CellValue::Null,
+        }
+    }
+
     fn get_as_string(&self, idx: usize) -> String {
-        self.data[idx].to_string()
+        if self.validity[idx] {
+            self.data[idx].to_string()
+        } else {
+            String::new()
+        }
     }
 
-    fn push_str(&mut self, val: &str) {
-        let parsed = val.parse::<i64>().expect("Invalid int");
-        self.data.push(parsed);
+    fn try_push(&mut self, val: CellValue) -> Result<(), DmError> {
+        match val {
+            CellValue::Null => self.push_null(),
+            CellValue::Int(v) | CellValue::DateTime(v) => IntColumn::push(self, v),
+            CellValue::Text(s) => return self.try_push_str(&s),
+            other => {
+                return Err(DmError::TypeMismatch {
+                    column: self.name.clone(),
+                    expected: ColumnType::Int,
+                    found: other.type_name().to_string(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    fn try_push_str(&mut self, val: &str) -> Result<(), DmError> {
+        // An empty cell has nothing to parse, so it is stored as null
+        if val.is_empty() {
+            self.push_null();
+            return Ok(());
+        }
+        let parsed = val.parse::<i64>().map_err(|_| DmError::ParseError {
+            row: self.data.len(),
+            column: self.name.clone(),
+            value: val.to_string(),
+        })?;
+        self.push(parsed);
+        Ok(())
+    }
+
+    fn is_null(&self, idx: usize) -> bool {
+        !self.validity[idx]
+    }
+
+    fn push_null(&mut self) {
+        self.data.push(0);
+        self.validity.push(false);
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.data.truncate(len);
+        self.validity.truncate(len);
     }
 
     fn clone_box(&self) -> Box<dyn ColumnTrait> {
@@ -47,4 +113,32 @@ impl ColumnTrait for IntColumn {
     fn get_type(&self) -> ColumnType {
         ColumnType::Int
     }
+
+    fn header(&self) -> Option<&ColumnHeader> {
+        self.header.as_ref()
+    }
+    fn set_header(&mut self, header: Option<ColumnHeader>) {
+        self.header = header;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_converts_or_rejects() {
+        let mut col = IntColumn::new("n");
+        col.try_push(CellValue::Int(1)).unwrap();
+        col.try_push(CellValue::Text("-2".into())).unwrap();
+        col.try_push(CellValue::DateTime(3)).unwrap();
+        assert_eq!((0..3).map(|i| ColumnTrait::get(&col, i)).collect::<Vec<_>>(), [CellValue::Int(1), CellValue::Int(-2), CellValue::Int(3)]);
+
+        assert!(matches!(col.try_push(CellValue::Float(1.0)), Err(DmError::TypeMismatch { .. })));
+        match col.try_push_str("1.5") {
+            Err(DmError::ParseError { row, column, value }) => assert_eq!((row, column.as_str(), value.as_str()), (3, "n", "1.5")),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(col.len(), 3);
+    }
 }
@@ -1,10 +1,15 @@
 use crate::columns::column_trait::ColumnTrait;
 use crate::columns::column_trait::ColumnType;
+use crate::columns::column_trait::ColumnHeader;
+use crate::cell_value::CellValue;
+use crate::error::DmError;
 
 #[derive(Clone)]
 pub struct StringColumn {
     name: String,
     data: Vec<String>,
+    validity: Vec<bool>,
+    header: Option<ColumnHeader>,
 }
 
 impl StringColumn {
@@ -12,14 +17,24 @@ impl StringColumn {
         Self {
             name: name.to_string(),
             data: Vec::new(),
+            validity: Vec::new(),
+            header: None,
         }
     }
     pub fn push(&mut self, val: &str) {
         self.data.push(val.to_string());
+        self.validity.push(true);
     }
     pub fn get(&self, idx: usize) -> &str {
         &self.data[idx]
     }
+    pub fn get_opt(&self, idx: usize) -> Option<&str> {
+        if self.validity[idx] {
+            Some(&self.data[idx])
+        } else {
+            None
+        }
+    }
 }
 
 impl ColumnTrait for StringColumn {
@@ -29,11 +44,40 @@ impl ColumnTrait for StringColumn {
     fn len(&self) -> usize {
         self.data.len()
     }
+    fn get(&self, idx: usize) -> CellValue {
+        match self.get_opt(idx) {
+            Some(v) => CellValue::Text(v.to_string()),
+            None => CellValue::Null,
+        }
+    }
     fn get_as_string(&self, idx: usize) -> String {
         self.data[idx].clone()
     }
-    fn push_str(&mut self, val: &str) {
-        self.data.push(val.to_string());
+    fn try_push(&mut self, val: CellValue) -> Result<(), DmError> {
+        match val {
+            CellValue::Null => self.push_null(),
+            CellValue::Text(s) => {
+                self.data.push(s);
+                self.validity.push(true);
+            }
+            other => StringColumn::push(self, &other.as_string()),
+        }
+        Ok(())
+    }
+    fn try_push_str(&mut self, val: &str) -> Result<(), DmError> {
+        StringColumn::push(self, val);
+        Ok(())
+    }
+    fn is_null(&self, idx: usize) -> bool {
+        !self.validity[idx]
+    }
+    fn push_null(&mut self) {
+        self.data.push(String::new());
+        self.validity.push(false);
+    }
+    fn truncate(&mut self, len: usize) {
+        self.data.truncate(len);
+        self.validity.truncate(len);
     }
     fn clone_box(&self) -> Box<dyn ColumnTrait> {
         Box::new(self.clone())
@@ -41,4 +85,26 @@ impl ColumnTrait for StringColumn {
     fn get_type(&self) -> ColumnType {
         ColumnType::String
     }
+    fn header(&self) -> Option<&ColumnHeader> {
+        self.header.as_ref()
+    }
+    fn set_header(&mut self, header: Option<ColumnHeader>) {
+        self.header = header;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_stores_any_value_as_text() {
+        let mut col = StringColumn::new("s");
+        col.try_push(CellValue::Text("a".into())).unwrap();
+        col.try_push(CellValue::Int(3)).unwrap();
+        col.try_push(CellValue::Float(1.5)).unwrap();
+        col.try_push_str("").unwrap();
+        let got: Vec<CellValue> = (0..4).map(|i| ColumnTrait::get(&col, i)).collect();
+        assert_eq!(got, ["a", "3", "1.5", ""].map(|s| CellValue::Text(s.to_string())));
+    }
 }
@@ -0,0 +1,74 @@
+use std::fmt;
+use crate::columns::column_trait::ColumnType;
+
+/// Errors returned by the fallible (`try_`) API of tables, columns and methods.
+#[derive(Debug)]
+pub enum DmError {
+    /// A referenced column does not exist in the table.
+    ColumnNotFound(String),
+    /// A column with the same name already exists in the table.
+    DuplicateColumn(String),
+    /// A value could not be parsed into the column's type.
+    ParseError { row: usize, column: String, value: String },
+    /// A value of the wrong type was given for a column.
+    TypeMismatch { column: String, expected: ColumnType, found: String },
+    /// Two lengths that must agree (e.g. a column and its table) differ.
+    LengthMismatch { expected: usize, found: usize },
+    /// An argument is invalid for the operation, e.g. unsorted input to a merge join.
+    InvalidArgument(String),
+    /// A join matched a row more often than the expected cardinality allows,
+    /// e.g. a duplicate key in the "one" side of a many-to-one join.
+    Cardinality { expected: String, side: String, row: usize, key: String, matches: usize },
+    /// An integer result, e.g. the sum of an integer column, does not fit in an `i64`.
+    Overflow { column: String },
+    /// A malformed CSV record, with the 1-based line it starts on.
+    Csv { line: usize, message: String },
+    /// Invalid or unsupported JSON, with the 1-based line when known.
+    Json { line: Option<usize>, message: String },
+    /// An underlying read or write failed.
+    Io(std::io::Error),
+}
+
+impl fmt::Display for DmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DmError::ColumnNotFound(name) => write!(f, "Column {} not found", name),
+            DmError::DuplicateColumn(name) => write!(f, "Column with name {} already exists", name),
+            DmError::ParseError { row, column, value } => {
+                write!(f, "Cannot parse {:?} in column {} at row {}", value, column, row)
+            }
+            DmError::TypeMismatch { column, expected, found } => {
+                write!(f, "Column {} expects {:?} values, found {}", column, expected, found)
+            }
+            DmError::LengthMismatch { expected, found } => {
+                write!(f, "Length mismatch: expected {}, found {}", expected, found)
+            }
+            DmError::InvalidArgument(message) => write!(f, "Invalid argument: {}", message),
+            DmError::Cardinality { expected, side, row, key, matches } => write!(
+                f,
+                "Join is not {}: {} row {} (key [{}]) matches {} {} rows",
+                expected, side, row, key, matches, if side == "left" { "right" } else { "left" }
+            ),
+            DmError::Overflow { column } => write!(f, "Integer overflow aggregating column {}", column),
+            DmError::Csv { line, message } => write!(f, "CSV error at line {}: {}", line, message),
+            DmError::Json { line: Some(line), message } => write!(f, "JSON error at line {}: {}", line, message),
+            DmError::Json { line: None, message } => write!(f, "JSON error: {}", message),
+            DmError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DmError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for DmError {
+    fn from(e: std::io::Error) -> Self {
+        DmError::Io(e)
+    }
+}
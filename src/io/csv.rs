@@ -0,0 +1,430 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use crate::cell_value::CellValue;
+use crate::columns::column_trait::ColumnType;
+use crate::columns::datetime_column::{format_datetime, parse_datetime};
+use crate::error::DmError;
+use crate::table::Table;
+
+/// Options for reading and writing CSV. Override single fields with struct update
+/// syntax, e.g. `CsvOptions { delimiter: ';', ..CsvOptions::default() }`.
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    /// Field separator.
+    pub delimiter: char,
+    /// Quote character, or `None` to disable quoting entirely.
+    pub quote: Option<char>,
+    /// Whether the first record holds the column names. Without a header,
+    /// columns are named by `schema` or `column_1`, `column_2`, ...
+    pub has_header: bool,
+    /// Explicit column types. With a header, types are looked up by name and
+    /// unlisted columns are inferred; without one, the schema names the columns in order.
+    pub schema: Option<Vec<(String, ColumnType)>>,
+    /// Number of records sampled to infer column types.
+    pub infer_rows: usize,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            quote: Some('"'),
+            has_header: true,
+            schema: None,
+            infer_rows: 100,
+        }
+    }
+}
+
+impl Table {
+    /// Read a table from CSV.
+    ///
+    /// Empty unquoted fields become nulls, while a quoted empty field (`""`) is an
+    /// empty string. Blank lines are skipped, except in a one-column table where
+    /// a blank line is a null, as [`Table::write_csv`] writes it. A leading UTF-8
+    /// byte order mark, as written by Excel, is skipped. Malformed records, unparsable cells and empty or repeated header
+    /// names are reported as `DmError::Csv` with the line they start on.
+    pub fn read_csv<R: Read>(reader: R, options: &CsvOptions) -> Result<Table, DmError> {
+        let mut records = RecordReader::new(BufReader::new(reader), options);
+
+        let (header_line, header): (usize, Option<Vec<String>>) = if options.has_header {
+            match records.next_record()? {
+                Some((line, fields)) => (line, Some(fields.into_iter().map(Option::unwrap_or_default).collect())),
+                None => return Ok(Table::new()),
+            }
+        } else {
+            (0, None)
+        };
+
+        // A one-column table writes a null as a blank line, so keep those
+        // lines once the width is known
+        let known_width = match (&header, &options.schema) {
+            (Some(names), _) => Some(names.len()),
+            (None, Some(schema)) => Some(schema.len()),
+            (None, None) => None,
+        };
+        records.blank_is_null = known_width == Some(1);
+
+        // Buffer a sample to infer types from
+        let mut sample = Vec::new();
+        while sample.len() < options.infer_rows.max(1) {
+            match records.next_record()? {
+                Some(record) => {
+                    if known_width.is_none() && sample.is_empty() {
+                        records.blank_is_null = record.1.len() == 1;
+                    }
+                    sample.push(record);
+                }
+                None => break,
+            }
+        }
+
+        let width = match (&header, &options.schema, sample.first()) {
+            (Some(names), _, _) => names.len(),
+            (None, Some(schema), _) => schema.len(),
+            (None, None, Some((_, fields))) => fields.len(),
+            (None, None, None) => return Ok(Table::new()),
+        };
+        let names: Vec<String> = match (header, &options.schema) {
+            (Some(names), _) => names,
+            (None, Some(schema)) => schema.iter().map(|(name, _)| name.clone()).collect(),
+            (None, None) => (1..=width).map(|i| format!("column_{}", i)).collect(),
+        };
+
+        let mut table = Table::new();
+        for (i, name) in names.iter().enumerate() {
+            if options.has_header && name.is_empty() {
+                return Err(DmError::Csv { line: header_line, message: format!("column {} has an empty name", i + 1) });
+            }
+            let declared = options.schema.as_ref().and_then(|schema| {
+                schema.iter().find(|(n, _)| n == name).map(|(_, t)| *t)
+            });
+            let col_type = declared.unwrap_or_else(|| {
+                infer_type(sample.iter().filter_map(|(_, fields)| fields.get(i).and_then(|f| f.as_deref())))
+            });
+            // only a header can repeat a name; a schema is reported as is
+            table.try_add_typed_column(name, col_type).map_err(|e| match e {
+                DmError::DuplicateColumn(_) if options.has_header => DmError::Csv { line: header_line, message: e.to_string() },
+                e => e,
+            })?;
+        }
+
+        for (line, fields) in sample {
+            push_record(&mut table, line, fields)?;
+        }
+        while let Some((line, fields)) = records.next_record()? {
+            push_record(&mut table, line, fields)?;
+        }
+
+        Ok(table)
+    }
+
+    /// Read a table from a CSV file. See [`Table::read_csv`].
+    pub fn read_csv_path<P: AsRef<Path>>(path: P, options: &CsvOptions) -> Result<Table, DmError> {
+        Table::read_csv(File::open(path)?, options)
+    }
+
+    /// Write the table as CSV. Nulls are written as empty fields and datetimes
+    /// in ISO 8601, so the output reads back into the same column types.
+    pub fn write_csv<W: Write>(&self, writer: W, options: &CsvOptions) -> Result<(), DmError> {
+        let mut writer = BufWriter::new(writer);
+        let mut line = 1;
+
+        if options.has_header {
+            let header = self.column_names().iter()
+                .map(|name| quote_field(name, options, line))
+                .collect::<Result<Vec<String>, DmError>>()?;
+            writeln!(writer, "{}", header.join(&options.delimiter.to_string()))?;
+            line += 1;
+        }
+
+        for i in 0..self.len() {
+            let mut fields = Vec::with_capacity(self.columns.len());
+            for c in &self.columns {
+                let field = match c.get(i) {
+                    CellValue::Null => String::new(),
+                    // `{:?}` keeps the decimal point so whole floats don't read back as ints
+                    CellValue::Float(f) => format!("{:?}", f),
+                    CellValue::DateTime(ts) => format_datetime(ts),
+                    // An empty string is quoted to tell it apart from a null
+                    CellValue::Text(s) if s.is_empty() && options.quote.is_some() => {
+                        let q = options.quote.unwrap();
+                        format!("{}{}", q, q)
+                    }
+                    other => quote_field(&other.as_string(), options, line)?,
+                };
+                fields.push(field);
+            }
+            writeln!(writer, "{}", fields.join(&options.delimiter.to_string()))?;
+            line += 1;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the table to a CSV file. See [`Table::write_csv`].
+    pub fn write_csv_path<P: AsRef<Path>>(&self, path: P, options: &CsvOptions) -> Result<(), DmError> {
+        self.write_csv(File::create(path)?, options)
+    }
+}
+
+// Pick the narrowest type that accepts every non-null sampled value
+fn infer_type<'a>(values: impl Iterator<Item = &'a str> + Clone) -> ColumnType {
+    if values.clone().next().is_none() {
+        ColumnType::String
+    } else if values.clone().all(|v| v.parse::<i64>().is_ok()) {
+        ColumnType::Int
+    } else if values.clone().all(|v| v.parse::<f64>().is_ok()) {
+        ColumnType::Float
+    } else if values.clone().all(|v| parse_datetime(v).is_some()) {
+        ColumnType::DateTime
+    } else {
+        ColumnType::String
+    }
+}
+
+fn push_record(table: &mut Table, line: usize, fields: Vec<Option<String>>) -> Result<(), DmError> {
+    if fields.len() != table.columns.len() {
+        return Err(DmError::Csv {
+            line,
+            message: format!("expected {} fields, found {}", table.columns.len(), fields.len()),
+        });
+    }
+    for (c, field) in table.columns.iter_mut().zip(fields) {
+        match field {
+            None => c.push_null(),
+            Some(val) => c.try_push_str(&val).map_err(|e| DmError::Csv { line, message: e.to_string() })?,
+        }
+    }
+    Ok(())
+}
+
+fn quote_field(val: &str, options: &CsvOptions, line: usize) -> Result<String, DmError> {
+    let needs_quotes = val.contains(options.delimiter)
+        || val.contains('\n')
+        || val.contains('\r')
+        || options.quote.is_some_and(|q| val.contains(q));
+    match options.quote {
+        _ if !needs_quotes => Ok(val.to_string()),
+        Some(q) => Ok(format!("{}{}{}", q, val.replace(q, &format!("{}{}", q, q)), q)),
+        None => Err(DmError::Csv {
+            line,
+            message: format!("value {:?} needs quoting but quoting is disabled", val),
+        }),
+    }
+}
+
+// A record's starting line and its fields. Fields are `None` when empty and unquoted.
+type Record = (usize, Vec<Option<String>>);
+
+// Splits a CSV stream into records, following quoted fields across lines.
+struct RecordReader<R> {
+    reader: R,
+    line: usize,
+    delimiter: char,
+    quote: Option<char>,
+    // Read a blank line as a record of one null field instead of skipping it
+    blank_is_null: bool,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    fn new(reader: R, options: &CsvOptions) -> Self {
+        Self {
+            reader,
+            line: 0,
+            delimiter: options.delimiter,
+            quote: options.quote,
+            blank_is_null: false,
+        }
+    }
+
+    fn next_record(&mut self) -> Result<Option<Record>, DmError> {
+        let mut buf = String::new();
+        // Skip blank lines, unless they stand for a null
+        loop {
+            buf.clear();
+            if self.reader.read_line(&mut buf)? == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+            if self.line == 1 && buf.starts_with('\u{feff}') {
+                buf.drain(..'\u{feff}'.len_utf8());
+            }
+            if !buf.trim_end_matches(['\r', '\n']).is_empty() {
+                break;
+            }
+            if self.blank_is_null {
+                return Ok(Some((self.line, vec![None])));
+            }
+        }
+        let start = self.line;
+
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut in_quotes = false;
+        let mut quoted = false;
+        loop {
+            let mut chars = buf.chars().peekable();
+            while let Some(c) = chars.next() {
+                if in_quotes {
+                    if Some(c) == self.quote {
+                        if chars.peek() == Some(&c) {
+                            field.push(c);
+                            chars.next();
+                        } else {
+                            in_quotes = false;
+                        }
+                    } else {
+                        field.push(c);
+                    }
+                } else if c == self.delimiter {
+                    fields.push(finish_field(&mut field, quoted));
+                    quoted = false;
+                } else if c == '\n' || c == '\r' {
+                    // end of the record
+                } else if Some(c) == self.quote && field.is_empty() && !quoted {
+                    in_quotes = true;
+                    quoted = true;
+                } else if quoted {
+                    return Err(DmError::Csv {
+                        line: self.line,
+                        message: format!("unexpected {:?} after closing quote", c),
+                    });
+                } else {
+                    field.push(c);
+                }
+            }
+            if !in_quotes {
+                break;
+            }
+            buf.clear();
+            if self.reader.read_line(&mut buf)? == 0 {
+                return Err(DmError::Csv { line: start, message: "unterminated quoted field".to_string() });
+            }
+            self.line += 1;
+        }
+        fields.push(finish_field(&mut field, quoted));
+
+        Ok(Some((start, fields)))
+    }
+}
+
+fn finish_field(field: &mut String, quoted: bool) -> Option<String> {
+    let val = std::mem::take(field);
+    if val.is_empty() && !quoted {
+        None
+    } else {
+        Some(val)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(input: &str) -> Result<Table, DmError> {
+        Table::read_csv(input.as_bytes(), &CsvOptions::default())
+    }
+
+    fn write(table: &Table, options: &CsvOptions) -> String {
+        let mut out = Vec::new();
+        table.write_csv(&mut out, options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn infers_column_types() {
+        let table = read("id,price,when,name\n1,1.5,2024-01-02T03:04:05Z,a\n2,2,2024-01-03,b\n").unwrap();
+        let types: Vec<ColumnType> = table.columns.iter().map(|c| c.get_type()).collect();
+        assert_eq!(types, [ColumnType::Int, ColumnType::Float, ColumnType::DateTime, ColumnType::String]);
+        assert_eq!(table.columns[1].get(1), CellValue::Float(2.0));
+    }
+
+    #[test]
+    fn quoted_fields_keep_delimiters_quotes_and_newlines() {
+        let table = read("a,b\n\"x, y\",\"say \"\"hi\"\"\"\n\"multi\nline\",z\n").unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.columns[0].get(0), CellValue::Text("x, y".to_string()));
+        assert_eq!(table.columns[1].get(0), CellValue::Text("say \"hi\"".to_string()));
+        assert_eq!(table.columns[0].get(1), CellValue::Text("multi\nline".to_string()));
+    }
+
+    #[test]
+    fn empty_field_is_null_and_quoted_empty_is_empty_string() {
+        let table = read("a,b\n,\"\"\n1,x\n").unwrap();
+        assert!(table.columns[0].is_null(0));
+        assert!(!table.columns[1].is_null(0));
+        assert_eq!(table.columns[1].get(0), CellValue::Text(String::new()));
+    }
+
+    #[test]
+    fn round_trip_preserves_values_and_types() {
+        let input = "id,price,note,when\n1,1.0,\"a,b\",2024-01-02T03:04:05Z\n2,,\"\",\n3,2.5,\"q\"\"uote\nx\",1970-01-01T00:00:00Z\n";
+        let table = read(input).unwrap();
+        let written = write(&table, &CsvOptions::default());
+        let again = read(&written).unwrap();
+        assert_eq!(again.column_names(), table.column_names());
+        for (a, b) in table.columns.iter().zip(&again.columns) {
+            assert_eq!(a.get_type(), b.get_type());
+            assert_eq!((0..a.len()).map(|i| a.get(i)).collect::<Vec<_>>(), (0..b.len()).map(|i| b.get(i)).collect::<Vec<_>>());
+        }
+        assert_eq!(written, write(&again, &CsvOptions::default()));
+
+        // a one-column table writes its nulls as blank lines
+        let single = read("id\n1\n\n3\n").unwrap();
+        let again = read(&write(&single, &CsvOptions::default())).unwrap();
+        assert_eq!(again.len(), 3);
+        assert_eq!(again.columns[0].get(1), CellValue::Null);
+    }
+
+    #[test]
+    fn blank_lines_are_nulls_only_in_one_column_tables() {
+        let input = "s\nx\n\n\"\"\n";
+        let table = read(input).unwrap();
+        assert_eq!(table.len(), 3);
+        assert!(table.columns[0].is_null(1));
+        assert_eq!(write(&table, &CsvOptions::default()), input);
+        // without a header the width comes from the first record
+        let options = CsvOptions { has_header: false, ..CsvOptions::default() };
+        assert_eq!(Table::read_csv("1\n\n3\n".as_bytes(), &options).unwrap().len(), 3);
+        // wider tables still skip blank lines
+        assert_eq!(read("a,b\n1,2\n\n3,4\n").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn custom_delimiter_round_trip() {
+        let options = CsvOptions { delimiter: ';', ..CsvOptions::default() };
+        let table = Table::read_csv("a;b\n1;x,y\n".as_bytes(), &options).unwrap();
+        assert_eq!(table.columns[1].get(0), CellValue::Text("x,y".to_string()));
+        assert_eq!(write(&table, &options), "a;b\n1;x,y\n");
+    }
+
+    #[test]
+    fn skips_byte_order_mark() {
+        let table = read("\u{feff}id,name\n1,a\n").unwrap();
+        assert_eq!(table.column_names(), ["id", "name"]);
+    }
+
+    #[test]
+    fn header_errors_report_the_line() {
+        assert!(matches!(read("a,a\n1,2\n"), Err(DmError::Csv { line: 1, .. })));
+        assert!(matches!(read("\na,\n1,2\n"), Err(DmError::Csv { line: 2, .. })));
+    }
+
+    #[test]
+    fn malformed_records_report_the_line() {
+        assert!(matches!(read("a,b\n1,2\n3\n"), Err(DmError::Csv { line: 3, .. })));
+        assert!(matches!(read("a\n\"open\n"), Err(DmError::Csv { line: 2, .. })));
+        assert!(matches!(read("a\n\"x\"y\n"), Err(DmError::Csv { line: 2, .. })));
+    }
+
+    #[test]
+    fn unquotable_value_without_quoting_fails() {
+        let table = read("a\n\"x,y\"\n").unwrap();
+        let mut out = Vec::new();
+        let options = CsvOptions { quote: None, ..CsvOptions::default() };
+        assert!(matches!(table.write_csv(&mut out, &options), Err(DmError::Csv { line: 2, .. })));
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufWriter, Write};
+use serde_json::{Map, Number, Value};
+use crate::cell_value::CellValue;
+use crate::columns::column_trait::ColumnType;
+use crate::columns::datetime_column::format_datetime;
+use crate::error::DmError;
+use crate::table::Table;
+
+impl Table {
+    /// Build a table from a JSON document, either row-oriented
+    /// (`[{"a": 1, "b": "x"}, ...]`) or column-oriented (`{"a": [1, ...], "b": ["x", ...]}`).
+    ///
+    /// Integers become `IntColumn`s, other numbers `FloatColumn`s (ints are widened
+    /// when a column mixes both), strings `StringColumn`s and `null` or missing keys
+    /// nulls. Columns mixing numbers and strings, booleans and nested values are
+    /// stored as strings. JSON has no datetime type, so the ISO 8601 strings the
+    /// writers produce for datetimes come back as `StringColumn`s.
+    pub fn from_json_records(json: &str) -> Result<Table, DmError> {
+        let value: Value = serde_json::from_str(json).map_err(json_error)?;
+        let mut builder = JsonTableBuilder::new();
+        match value {
+            Value::Array(rows) => {
+                for (i, row) in rows.iter().enumerate() {
+                    match row {
+                        Value::Object(obj) => builder.push_object(obj),
+                        _ => return Err(DmError::Json { line: None, message: format!("record {} is not an object", i) }),
+                    }
+                }
+            }
+            Value::Object(columns) => {
+                for (name, values) in &columns {
+                    match values {
+                        Value::Array(values) => builder.push_column(name, values)?,
+                        _ => return Err(DmError::Json { line: None, message: format!("column {} is not an array", name) }),
+                    }
+                }
+            }
+            _ => return Err(DmError::Json { line: None, message: "expected an array of records or an object of columns".to_string() }),
+        }
+        builder.build()
+    }
+
+    /// Build a table from newline-delimited JSON, one object per line, reading the
+    /// input line by line. Types are mapped as in [`Table::from_json_records`].
+    pub fn from_ndjson<R: BufRead>(reader: R) -> Result<Table, DmError> {
+        let mut builder = JsonTableBuilder::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let value: Value = serde_json::from_str(&line).map_err(|e| DmError::Json { line: Some(i + 1), message: e.to_string() })?;
+            match value {
+                Value::Object(obj) => builder.push_object(&obj),
+                _ => return Err(DmError::Json { line: Some(i + 1), message: "expected an object".to_string() }),
+            }
+        }
+        builder.build()
+    }
+
+    /// Write the table as a JSON array of objects, one per row.
+    pub fn write_json_records<W: Write>(&self, writer: W) -> Result<(), DmError> {
+        let rows: Vec<Value> = (0..self.len()).map(|i| Value::Object(self.row_to_json(i))).collect();
+        serde_json::to_writer(writer, &rows).map_err(json_error)
+    }
+
+    /// Write the table as a JSON object mapping each column name to its values.
+    pub fn write_json_columns<W: Write>(&self, writer: W) -> Result<(), DmError> {
+        let columns: Map<String, Value> = self.columns.iter()
+            .map(|c| (c.name().to_string(), Value::Array((0..c.len()).map(|i| cell_to_json(c.get(i))).collect())))
+            .collect();
+        serde_json::to_writer(writer, &columns).map_err(json_error)
+    }
+
+    /// Write the table as newline-delimited JSON, one object per row.
+    pub fn write_ndjson<W: Write>(&self, writer: W) -> Result<(), DmError> {
+        let mut writer = BufWriter::new(writer);
+        for i in 0..self.len() {
+            serde_json::to_writer(&mut writer, &self.row_to_json(i)).map_err(json_error)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub(crate) fn row_to_json(&self, row: usize) -> Map<String, Value> {
+        self.columns.iter().map(|c| (c.name().to_string(), cell_to_json(c.get(row)))).collect()
+    }
+}
+
+/// Convert a cell to JSON. Datetimes are written as ISO 8601 strings and
+/// non-finite floats, which JSON cannot represent, as `null`.
+pub(crate) fn cell_to_json(val: CellValue) -> Value {
+    match val {
+        CellValue::Null => Value::Null,
+        CellValue::Int(i) => Value::from(i),
+        CellValue::Float(f) => Number::from_f64(f).map(Value::Number).unwrap_or(Value::Null),
+        CellValue::Text(s) => Value::String(s),
+        CellValue::DateTime(ts) => Value::String(format_datetime(ts)),
+    }
+}
+
+fn json_to_cell(val: &Value) -> CellValue {
+    match val {
+        Value::Null => CellValue::Null,
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => CellValue::Int(i),
+            None => CellValue::Float(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => CellValue::Text(s.clone()),
+        other => CellValue::Text(other.to_string()),
+    }
+}
+
+pub(crate) fn json_error(e: serde_json::Error) -> DmError {
+    let line = if e.line() > 0 { Some(e.line()) } else { None };
+    DmError::Json { line, message: e.to_string() }
+}
+
+// Collects JSON values column by column, widening each column's type as values arrive
+pub(crate) struct JsonTableBuilder {
+    columns: Vec<(String, Option<ColumnType>, Vec<CellValue>)>,
+    index: HashMap<String, usize>,
+    rows: usize,
+}
+
+impl JsonTableBuilder {
+    pub(crate) fn new() -> Self {
+        Self { columns: Vec::new(), index: HashMap::new(), rows: 0 }
+    }
+
+    pub(crate) fn push_object(&mut self, obj: &Map<String, Value>) {
+        for (name, val) in obj {
+            let idx = self.column(name);
+            let (_, col_type, values) = &mut self.columns[idx];
+            let cell = json_to_cell(val);
+            *col_type = widen(*col_type, &cell);
+            values.push(cell);
+        }
+        self.rows += 1;
+        // keys missing from this object are null
+        for (_, _, values) in self.columns.iter_mut() {
+            values.resize(self.rows, CellValue::Null);
+        }
+    }
+
+    fn push_column(&mut self, name: &str, vals: &[Value]) -> Result<(), DmError> {
+        if !self.columns.is_empty() && vals.len() != self.rows {
+            return Err(DmError::LengthMismatch { expected: self.rows, found: vals.len() });
+        }
+        let mut col_type = None;
+        let mut values = Vec::with_capacity(vals.len());
+        for val in vals {
+            let cell = json_to_cell(val);
+            col_type = widen(col_type, &cell);
+            values.push(cell);
+        }
+        self.index.insert(name.to_string(), self.columns.len());
+        self.columns.push((name.to_string(), col_type, values));
+        self.rows = vals.len();
+        Ok(())
+    }
+
+    // Index of the named column, adding it (back-filled with nulls) if new
+    fn column(&mut self, name: &str) -> usize {
+        if let Some(&idx) = self.index.get(name) {
+            return idx;
+        }
+        self.columns.push((name.to_string(), None, vec![CellValue::Null; self.rows]));
+        self.index.insert(name.to_string(), self.columns.len() - 1);
+        self.columns.len() - 1
+    }
+
+    pub(crate) fn build(self) -> Result<Table, DmError> {
+        let mut table = Table::new();
+        for (name, col_type, values) in self.columns {
+            // a column that only ever held nulls is stored as strings
+            let mut col = col_type.unwrap_or(ColumnType::String).new_column(&name);
+            for val in values {
+                col.try_push(val)?;
+            }
+            table.try_add_column(col)?;
+        }
+        Ok(table)
+    }
+}
+
+fn widen(current: Option<ColumnType>, cell: &CellValue) -> Option<ColumnType> {
+    let incoming = match cell {
+        CellValue::Null => return current,
+        CellValue::Int(_) => ColumnType::Int,
+        CellValue::Float(_) => ColumnType::Float,
+        _ => ColumnType::String,
+    };
+    Some(match (current, incoming) {
+        (None, t) => t,
+        (Some(a), b) if a == b => a,
+        (Some(ColumnType::Int), ColumnType::Float) | (Some(ColumnType::Float), ColumnType::Int) => ColumnType::Float,
+        _ => ColumnType::String,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::fixtures::{table, values};
+
+    fn sample() -> Table {
+        table(&[
+            ("id", ColumnType::Int, vec![CellValue::Int(1), CellValue::Null]),
+            ("x", ColumnType::Float, vec![CellValue::Float(0.5), CellValue::Float(2.0)]),
+            ("s", ColumnType::String, vec![CellValue::Text("a".into()), CellValue::Text(String::new())]),
+        ])
+    }
+
+    fn written(write: impl FnOnce(&mut Vec<u8>) -> Result<(), DmError>) -> String {
+        let mut out = Vec::new();
+        write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn types(t: &Table) -> Vec<ColumnType> {
+        t.columns.iter().map(|c| c.get_type()).collect()
+    }
+
+    #[test]
+    fn rows_columns_and_ndjson_round_trip() {
+        let t = sample();
+        let records = written(|w| t.write_json_records(w));
+        assert_eq!(records, r#"[{"id":1,"x":0.5,"s":"a"},{"id":null,"x":2.0,"s":""}]"#);
+        let columns = written(|w| t.write_json_columns(w));
+        assert_eq!(columns, r#"{"id":[1,null],"x":[0.5,2.0],"s":["a",""]}"#);
+        let ndjson = written(|w| t.write_ndjson(w));
+
+        for back in [
+            Table::from_json_records(&records).unwrap(),
+            Table::from_json_records(&columns).unwrap(),
+            Table::from_ndjson(ndjson.as_bytes()).unwrap(),
+        ] {
+            assert_eq!(back.column_names(), t.column_names());
+            assert_eq!(types(&back), types(&t));
+            assert_eq!(back.get_rows_typed(), t.get_rows_typed());
+        }
+    }
+
+    #[test]
+    fn types_widen_as_values_arrive() {
+        let t = Table::from_json_records(r#"[{"n": 1, "m": 1, "b": true, "o": null}, {"n": 2.5, "m": "x", "b": false}, {"n": 3}]"#).unwrap();
+        assert_eq!(types(&t), [ColumnType::Float, ColumnType::String, ColumnType::String, ColumnType::String]);
+        assert_eq!(values(&t, "n"), [CellValue::Float(1.0), CellValue::Float(2.5), CellValue::Float(3.0)]);
+        assert_eq!(values(&t, "m"), [CellValue::Text("1".into()), CellValue::Text("x".into()), CellValue::Null]);
+        assert_eq!(values(&t, "b")[0], CellValue::Text("true".into()));
+        assert_eq!(values(&t, "o"), vec![CellValue::Null; 3]);
+    }
+
+    #[test]
+    fn missing_keys_are_null() {
+        let t = Table::from_json_records(r#"[{"a": 1}, {"b": "x"}]"#).unwrap();
+        assert_eq!(values(&t, "a"), [CellValue::Int(1), CellValue::Null]);
+        assert_eq!(values(&t, "b"), [CellValue::Null, CellValue::Text("x".into())]);
+    }
+
+    #[test]
+    fn datetimes_come_back_as_strings() {
+        let t = table(&[("t", ColumnType::DateTime, vec![CellValue::DateTime(0)])]);
+        let back = Table::from_json_records(&written(|w| t.write_json_records(w))).unwrap();
+        assert_eq!(values(&back, "t"), [CellValue::Text("1970-01-01T00:00:00Z".into())]);
+    }
+
+    #[test]
+    fn malformed_input_is_reported() {
+        assert!(matches!(Table::from_json_records(r#"{"a": [1, 2], "b": [1]}"#), Err(DmError::LengthMismatch { expected: 2, found: 1 })));
+        assert!(matches!(Table::from_json_records("[1]"), Err(DmError::Json { line: None, .. })));
+        assert!(matches!(Table::from_ndjson("{\"a\": 1}\n\n{\"a\": }\n".as_bytes()), Err(DmError::Json { line: Some(3), .. })));
+        assert!(matches!(Table::from_ndjson("[1]\n".as_bytes()), Err(DmError::Json { line: Some(1), .. })));
+    }
+}
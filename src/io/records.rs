@@ -0,0 +1,186 @@
+use serde::de::{self, DeserializeOwned};
+use serde::ser::SerializeSeq;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use crate::cell_value::CellValue;
+use crate::columns::column_trait::{ColumnHeader, ColumnTrait, ColumnType};
+use crate::error::DmError;
+use crate::io::json::{json_error, JsonTableBuilder};
+use crate::table::Table;
+
+/// A table serializes as its list of columns, each with its name, type and values:
+/// `{"columns": [{"name": "sales", "type": "Float", "values": [10.0, null]}]}`.
+/// Pivoted columns also carry their header, its key parts tagged with their type:
+/// `"header": {"value_column": "sales", "aggregation": "Sum", "key": [{"type": "Int", "value": 2024}]}`.
+impl Serialize for Table {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let columns: Vec<ColumnRef> = self.columns.iter().map(|c| ColumnRef(c.as_ref())).collect();
+        TableRef { columns }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Table {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = TableData::deserialize(deserializer)?;
+        let mut table = Table::new();
+        for column in data.columns {
+            let mut col = column.col_type.new_column(&column.name);
+            col.set_header(column.header.map(HeaderData::into_header));
+            for val in column.values {
+                col.try_push(val).map_err(de::Error::custom)?;
+            }
+            table.try_add_column(col).map_err(de::Error::custom)?;
+        }
+        Ok(table)
+    }
+}
+
+#[derive(Serialize)]
+struct TableRef<'a> {
+    columns: Vec<ColumnRef<'a>>,
+}
+
+struct ColumnRef<'a>(&'a dyn ColumnTrait);
+
+impl Serialize for ColumnRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Column<'a> {
+            name: &'a str,
+            #[serde(rename = "type")]
+            col_type: ColumnType,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            header: Option<HeaderData>,
+            values: Values<'a>,
+        }
+        let header = self.0.header().map(HeaderData::from_header);
+        Column { name: self.0.name(), col_type: self.0.get_type(), header, values: Values(self.0) }.serialize(serializer)
+    }
+}
+
+// Streams a column's cells without collecting them first
+struct Values<'a>(&'a dyn ColumnTrait);
+
+impl Serialize for Values<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for i in 0..self.0.len() {
+            seq.serialize_element(&self.0.get(i))?;
+        }
+        seq.end()
+    }
+}
+
+#[derive(Deserialize)]
+struct TableData {
+    columns: Vec<ColumnData>,
+}
+
+#[derive(Deserialize)]
+struct ColumnData {
+    name: String,
+    #[serde(rename = "type")]
+    col_type: ColumnType,
+    #[serde(default)]
+    header: Option<HeaderData>,
+    values: Vec<CellValue>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HeaderData {
+    value_column: String,
+    aggregation: String,
+    key: Vec<Option<KeyPart>>,
+}
+
+// A non-null key part with its type, as a bare datetime would read back as an int
+#[derive(Serialize, Deserialize)]
+struct KeyPart {
+    #[serde(rename = "type")]
+    col_type: ColumnType,
+    value: CellValue,
+}
+
+impl HeaderData {
+    fn from_header(header: &ColumnHeader) -> Self {
+        let key = header.key.iter()
+            .map(|v| {
+                let col_type = match v {
+                    CellValue::Null => return None,
+                    CellValue::Int(_) => ColumnType::Int,
+                    CellValue::Float(_) => ColumnType::Float,
+                    CellValue::Text(_) => ColumnType::String,
+                    CellValue::DateTime(_) => ColumnType::DateTime,
+                };
+                Some(KeyPart { col_type, value: v.clone() })
+            })
+            .collect();
+        Self { value_column: header.value_column.clone(), aggregation: header.aggregation.clone(), key }
+    }
+
+    fn into_header(self) -> ColumnHeader {
+        let key = self.key.into_iter()
+            .map(|part| match part {
+                None => CellValue::Null,
+                Some(KeyPart { col_type: ColumnType::DateTime, value: CellValue::Int(ts) }) => CellValue::DateTime(ts),
+                Some(KeyPart { col_type: ColumnType::Float, value: CellValue::Int(i) }) => CellValue::Float(i as f64),
+                Some(KeyPart { value, .. }) => value,
+            })
+            .collect();
+        ColumnHeader { value_column: self.value_column, aggregation: self.aggregation, key }
+    }
+}
+
+impl Table {
+    /// Build a table from serializable records, one row per record, with one column
+    /// per field. Column types follow the rules of [`Table::from_json_records`].
+    pub fn from_records<T: Serialize>(records: &[T]) -> Result<Table, DmError> {
+        let mut builder = JsonTableBuilder::new();
+        for (i, record) in records.iter().enumerate() {
+            match serde_json::to_value(record).map_err(json_error)? {
+                Value::Object(obj) => builder.push_object(&obj),
+                _ => return Err(DmError::Json { line: None, message: format!("record {} is not a struct or map", i) }),
+            }
+        }
+        builder.build()
+    }
+
+    /// Deserialize every row into a record, matching fields by column name.
+    /// Datetimes are handed over as ISO 8601 strings.
+    pub fn to_records<T: DeserializeOwned>(&self) -> Result<Vec<T>, DmError> {
+        (0..self.len())
+            .map(|i| serde_json::from_value(Value::Object(self.row_to_json(i))).map_err(json_error))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::methods::aggregation::AggregationType;
+    use crate::methods::pivot::pivot;
+    use crate::table::fixtures::table;
+
+    #[test]
+    fn pivot_headers_round_trip() {
+        let t = table(&[
+            ("r", ColumnType::String, vec![CellValue::Text("a".into()), CellValue::Text("b".into())]),
+            ("d", ColumnType::DateTime, vec![CellValue::DateTime(86_400), CellValue::Null]),
+            ("v", ColumnType::Float, vec![CellValue::Float(1.5), CellValue::Float(2.0)]),
+        ]);
+        let pivoted = pivot(&t, &["r"], &["d"], "v", AggregationType::Sum);
+
+        let json = serde_json::to_string(&pivoted).unwrap();
+        let back: Table = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.column_names(), pivoted.column_names());
+        for (a, b) in back.columns.iter().zip(&pivoted.columns) {
+            assert_eq!(a.header(), b.header());
+        }
+        assert_eq!(back.columns[2].header().unwrap().key, [CellValue::DateTime(86_400)]);
+        assert_eq!(format!("{:?}", back), format!("{:?}", pivoted));
+
+        // plain columns have no header field
+        let plain = serde_json::to_value(&t).unwrap();
+        assert!(plain["columns"][0].get("header").is_none());
+    }
+}
@@ -1,4 +1,6 @@
 pub mod cell_value;
 pub mod columns;
+pub mod error;
+pub mod io;
 pub mod methods;
-pub mod table;
\ No newline at end of file
+pub mod table;
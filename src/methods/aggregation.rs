@@ -1,36 +1,382 @@
+use crate::cell_value::CellValue;
+use crate::columns::column_trait::{ColumnTrait, ColumnType};
+use crate::error::DmError;
 use crate::table::Table;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+use std::sync::Arc;
 
-#[derive(Debug, Copy, Clone)]
+/// Aggregation functions. Null cells are skipped by all of them. The arithmetic
+/// ones (Sum, Mean, Median, Quantile, StdDev, Variance) need numbers: int and
+/// float values, or text that parses as a number.
+#[derive(Debug, Clone)]
 pub enum AggregationType {
+    /// Sum of the values, 0 for a group without values. Integer columns sum
+    /// to an integer, failing with `DmError::Overflow` past `i64`
     Sum,
+    /// Number of non-null values, as an integer
     Count,
-    // Add more (Avg, Min, Max) as needed
+    /// Arithmetic mean
+    Mean,
+    /// Smallest value; works on any column type. NaN floats are skipped
+    Min,
+    /// Largest value; works on any column type. NaN floats are skipped
+    Max,
+    /// Middle value, averaging the two middle values for an even count
+    Median,
+    /// Value at the given fraction (0.0 to 1.0) of the sorted values,
+    /// interpolating linearly between neighbours like pandas
+    Quantile(f64),
+    /// Sample standard deviation (n - 1 denominator)
+    StdDev,
+    /// Sample variance (n - 1 denominator)
+    Variance,
+    /// Number of distinct non-null values, as an integer; works on any column type
+    CountDistinct,
+    /// First non-null value in row order; works on any column type
+    First,
+    /// Last non-null value in row order; works on any column type
+    Last,
+    /// A user-defined [`Aggregator`], created with [`AggregationType::custom`]
+    Custom(CustomAggregation),
 }
 
-pub fn aggregate(table: &Table, groups: &HashMap<String, Vec<usize>>, column: &str, agg_type: AggregationType) -> HashMap<String, f64> {
-    let col_idx = table.get_column_index(column).expect("Aggregation column not found");
-    let c = &table.columns[col_idx];
+/// A user-defined aggregation, e.g. a weighted average or a geometric mean.
+///
+/// Each group starts from [`Aggregator::init`], is fed its non-null values with
+/// [`Aggregator::update`] and produces its result with [`Aggregator::finalize`].
+/// [`Aggregator::merge`] combines states built over disjoint sets of rows:
+/// [`aggregate_row_chunks`] builds one state per chunk of rows and merges them
+/// into the group's state.
+pub trait Aggregator: Send + Sync + 'static {
+    /// Running state of one group.
+    type State: Send;
 
-    // For simplicity, parse values as floats
-    let mut result = HashMap::new();
+    /// Name shown in debug output and default column names.
+    fn name(&self) -> &str;
 
-    for (key, rows) in groups {
-        match agg_type {
-            AggregationType::Count => {
-                result.insert(key.clone(), rows.len() as f64);
+    /// Type of the finalized values for a column of type `source`.
+    fn output_type(&self, source: ColumnType) -> ColumnType;
+
+    /// State of a group without values.
+    fn init(&self) -> Self::State;
+
+    /// Add a non-null value of the aggregated column. `table` and `row` give
+    /// access to the rest of the row, e.g. a weight column.
+    fn update(&self, state: &mut Self::State, value: &CellValue, table: &Table, row: usize) -> Result<(), DmError>;
+
+    /// Fold `other`, built over other rows of the same group, into `state`.
+    fn merge(&self, state: &mut Self::State, other: Self::State);
+
+    /// Turn the state into the group's result.
+    fn finalize(&self, state: Self::State) -> CellValue;
+}
+
+// Object-safe view of an `Aggregator`, hiding its state type
+trait DynAggregator: Send + Sync {
+    fn name(&self) -> &str;
+    fn output_type(&self, source: ColumnType) -> ColumnType;
+    fn aggregate(&self, table: &Table, chunks: &[&[usize]], column: usize) -> Result<CellValue, DmError>;
+}
+
+impl<A: Aggregator> DynAggregator for A {
+    fn name(&self) -> &str {
+        Aggregator::name(self)
+    }
+
+    fn output_type(&self, source: ColumnType) -> ColumnType {
+        Aggregator::output_type(self, source)
+    }
+
+    // One state per chunk, merged in chunk order
+    fn aggregate(&self, table: &Table, chunks: &[&[usize]], column: usize) -> Result<CellValue, DmError> {
+        let c = &table.columns[column];
+        let mut merged: Option<A::State> = None;
+        for rows in chunks {
+            let mut state = self.init();
+            for &r in rows.iter().filter(|&&r| !c.is_null(r)) {
+                self.update(&mut state, &c.get(r), table, r)?;
+            }
+            match merged.as_mut() {
+                Some(m) => self.merge(m, state),
+                None => merged = Some(state),
             }
-            AggregationType::Sum => {
-                let mut sum = 0.0;
-                for &r in rows {
-                    let val_str = c.get_as_string(r);
-                    let val: f64 = val_str.parse().expect("Non-numeric value in sum");
-                    sum += val;
+        }
+        Ok(self.finalize(merged.unwrap_or_else(|| self.init())))
+    }
+}
+
+/// A shared handle to a user-defined [`Aggregator`].
+#[derive(Clone)]
+pub struct CustomAggregation(Arc<dyn DynAggregator>);
+
+impl CustomAggregation {
+    pub fn name(&self) -> &str {
+        self.0.name()
+    }
+}
+
+impl fmt::Debug for CustomAggregation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.name())
+    }
+}
+
+impl AggregationType {
+    /// Wrap a user-defined aggregator so it can be used wherever the built-ins are.
+    pub fn custom(aggregator: impl Aggregator) -> Self {
+        AggregationType::Custom(CustomAggregation(Arc::new(aggregator)))
+    }
+
+    /// Type of the aggregated values for a column of type `source`: Min, Max,
+    /// First and Last keep the column type, counts are integers, sums of
+    /// integers stay integers and everything else produces floats.
+    pub fn output_type(&self, source: ColumnType) -> ColumnType {
+        match self {
+            AggregationType::Min | AggregationType::Max | AggregationType::First | AggregationType::Last => source,
+            AggregationType::Count | AggregationType::CountDistinct => ColumnType::Int,
+            AggregationType::Sum if source == ColumnType::Int => ColumnType::Int,
+            AggregationType::Custom(custom) => custom.0.output_type(source),
+            _ => ColumnType::Float,
+        }
+    }
+}
+
+pub fn try_aggregate<K: Clone + Eq + Hash>(table: &Table, groups: &HashMap<K, Vec<usize>>, column: &str, agg_type: AggregationType) -> Result<HashMap<K, CellValue>, DmError> {
+    let col_idx = table.try_get_column_index(column)?;
+
+    let mut result = HashMap::new();
+    for (key, rows) in groups {
+        result.insert(key.clone(), aggregate_rows(table, rows, col_idx, &agg_type)?);
+    }
+    Ok(result)
+}
+
+pub fn aggregate<K: Clone + Eq + Hash>(table: &Table, groups: &HashMap<K, Vec<usize>>, column: &str, agg_type: AggregationType) -> HashMap<K, CellValue> {
+    try_aggregate(table, groups, column, agg_type).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Aggregate the given rows of a table's column into a value of
+/// [`AggregationType::output_type`]. Null cells are skipped; a group
+/// without values gives 0 for Sum, Count and CountDistinct and null otherwise,
+/// as does StdDev or Variance of a single value.
+///
+/// Returns `DmError::TypeMismatch` when an arithmetic aggregation meets a
+/// datetime or a text that is not a number, `DmError::Overflow` when an integer sum overflows and
+/// `DmError::InvalidArgument` for a quantile outside 0.0 to 1.0.
+pub fn aggregate_rows(table: &Table, rows: &[usize], column_idx: usize, agg_type: &AggregationType) -> Result<CellValue, DmError> {
+    let column: &dyn ColumnTrait = table.columns[column_idx].as_ref();
+    let values = || rows.iter().copied().filter(|&r| !column.is_null(r));
+    let mismatch = |found: String| DmError::TypeMismatch { column: column.name().to_string(), expected: ColumnType::Float, found };
+    let numbers = || -> Result<Vec<f64>, DmError> {
+        values()
+            .map(|r| match column.get(r) {
+                CellValue::Int(i) => Ok(i as f64),
+                CellValue::Float(f) => Ok(f),
+                CellValue::Text(s) => s.parse().map_err(|_| mismatch(format!("text {:?}", s))),
+                other => Err(mismatch(other.type_name().to_string())),
+            })
+            .collect()
+    };
+    // NaN sorts above every float, so it would always be the maximum
+    let ordered = || values().map(|r| column.get(r)).filter(|v| !matches!(v, CellValue::Float(f) if f.is_nan()));
+
+    let result = match agg_type {
+        AggregationType::Count => CellValue::Int(values().count() as i64),
+        AggregationType::CountDistinct => {
+            let distinct: HashSet<CellValue> = values().map(|r| column.get(r)).collect();
+            CellValue::Int(distinct.len() as i64)
+        }
+        AggregationType::Sum if column.get_type() == ColumnType::Int => {
+            let mut sum: i64 = 0;
+            for r in values() {
+                if let CellValue::Int(i) = column.get(r) {
+                    sum = sum.checked_add(i).ok_or_else(|| DmError::Overflow { column: column.name().to_string() })?;
                 }
-                result.insert(key.clone(), sum);
             }
+            CellValue::Int(sum)
+        }
+        // `Sum` for floats starts from -0.0, so fold from 0.0 for empty groups
+        AggregationType::Sum => CellValue::Float(numbers()?.iter().fold(0.0, |acc, x| acc + x)),
+        AggregationType::Mean => {
+            let nums = numbers()?;
+            float_or_null(!nums.is_empty(), || nums.iter().sum::<f64>() / nums.len() as f64)
+        }
+        AggregationType::Median => quantile(numbers()?, 0.5),
+        AggregationType::Quantile(p) => {
+            let p = *p;
+            if !(0.0..=1.0).contains(&p) {
+                return Err(DmError::InvalidArgument(format!("quantile {} is outside 0.0 to 1.0", p)));
+            }
+            quantile(numbers()?, p)
+        }
+        AggregationType::Variance => {
+            let nums = numbers()?;
+            float_or_null(nums.len() > 1, || variance(&nums))
         }
+        AggregationType::StdDev => {
+            let nums = numbers()?;
+            float_or_null(nums.len() > 1, || variance(&nums).sqrt())
+        }
+        AggregationType::Min => ordered().min().unwrap_or(CellValue::Null),
+        AggregationType::Max => ordered().max().unwrap_or(CellValue::Null),
+        AggregationType::First => values().next().map_or(CellValue::Null, |r| column.get(r)),
+        AggregationType::Last => values().last().map_or(CellValue::Null, |r| column.get(r)),
+        AggregationType::Custom(custom) => custom.0.aggregate(table, &[rows], column_idx)?,
+    };
+    Ok(result)
+}
+
+/// Aggregate a group whose rows come in several chunks, e.g. batches appended
+/// to a table over time. A custom aggregation builds one state per chunk and
+/// combines them with [`Aggregator::merge`]; the built-ins aggregate the chunks'
+/// rows in chunk order. With a `merge` that is consistent with `update`, the
+/// result is that of [`aggregate_rows`] over the concatenated chunks.
+pub fn aggregate_row_chunks(table: &Table, chunks: &[&[usize]], column_idx: usize, agg_type: &AggregationType) -> Result<CellValue, DmError> {
+    match agg_type {
+        AggregationType::Custom(custom) => custom.0.aggregate(table, chunks, column_idx),
+        _ => aggregate_rows(table, &chunks.concat(), column_idx, agg_type),
+    }
+}
+
+fn float_or_null(defined: bool, f: impl FnOnce() -> f64) -> CellValue {
+    if defined {
+        CellValue::Float(f())
+    } else {
+        CellValue::Null
+    }
+}
+
+fn variance(nums: &[f64]) -> f64 {
+    let mean = nums.iter().sum::<f64>() / nums.len() as f64;
+    nums.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (nums.len() - 1) as f64
+}
+
+// Linear interpolation between the closest ranks
+fn quantile(mut nums: Vec<f64>, p: f64) -> CellValue {
+    if nums.is_empty() {
+        return CellValue::Null;
+    }
+    nums.sort_by(f64::total_cmp);
+    let pos = p * (nums.len() - 1) as f64;
+    let (lo, hi) = (pos.floor() as usize, pos.ceil() as usize);
+    CellValue::Float(nums[lo] + (nums[hi] - nums[lo]) * (pos - lo as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::fixtures::table;
+
+    // A table of one column, `v`
+    fn column(col_type: ColumnType, values: Vec<CellValue>) -> Table {
+        table(&[("v", col_type, values)])
+    }
+
+    fn floats(values: &[f64]) -> Table {
+        column(ColumnType::Float, values.iter().map(|&f| CellValue::Float(f)).collect())
+    }
+
+    fn agg(t: &Table, agg_type: AggregationType) -> Result<CellValue, DmError> {
+        aggregate_rows(t, &(0..t.len()).collect::<Vec<_>>(), 0, &agg_type)
+    }
+
+    #[test]
+    fn quantile_interpolates_between_ranks() {
+        let t = floats(&[4.0, 1.0, 3.0, 2.0]);
+        assert_eq!(agg(&t, AggregationType::Quantile(0.0)).unwrap(), CellValue::Float(1.0));
+        assert_eq!(agg(&t, AggregationType::Quantile(0.25)).unwrap(), CellValue::Float(1.75));
+        assert_eq!(agg(&t, AggregationType::Quantile(0.5)).unwrap(), CellValue::Float(2.5));
+        assert_eq!(agg(&t, AggregationType::Quantile(1.0)).unwrap(), CellValue::Float(4.0));
+        assert_eq!(agg(&t, AggregationType::Median).unwrap(), CellValue::Float(2.5));
+        assert_eq!(agg(&floats(&[7.0]), AggregationType::Quantile(0.9)).unwrap(), CellValue::Float(7.0));
+        assert!(matches!(agg(&t, AggregationType::Quantile(1.5)), Err(DmError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn empty_groups_and_nulls() {
+        let t = column(ColumnType::Int, vec![CellValue::Null, CellValue::Int(2)]);
+        assert_eq!(agg(&t, AggregationType::Count).unwrap(), CellValue::Int(1));
+        assert_eq!(agg(&t, AggregationType::Variance).unwrap(), CellValue::Null);
+        let empty = floats(&[]);
+        assert_eq!(agg(&empty, AggregationType::Sum).unwrap(), CellValue::Float(0.0));
+        assert_eq!(agg(&empty, AggregationType::Median).unwrap(), CellValue::Null);
     }
 
-    result
+    #[test]
+    fn sample_variance_and_std_dev() {
+        let t = floats(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(agg(&t, AggregationType::Variance).unwrap(), CellValue::Float(32.0 / 7.0));
+        assert_eq!(agg(&t, AggregationType::StdDev).unwrap(), CellValue::Float((32.0f64 / 7.0).sqrt()));
+    }
+
+    #[test]
+    fn arithmetic_rejects_datetimes_and_non_numeric_text() {
+        let dates = column(ColumnType::DateTime, vec![CellValue::DateTime(1), CellValue::DateTime(2)]);
+        for agg_type in [AggregationType::Sum, AggregationType::Mean, AggregationType::Median, AggregationType::StdDev] {
+            assert!(matches!(agg(&dates, agg_type), Err(DmError::TypeMismatch { .. })));
+        }
+        assert_eq!(agg(&dates, AggregationType::Max).unwrap(), CellValue::DateTime(2));
+
+        let text = column(ColumnType::String, vec![CellValue::Text("1.5".into()), CellValue::Text("2.5".into())]);
+        assert_eq!(agg(&text, AggregationType::Mean).unwrap(), CellValue::Float(2.0));
+        let text = column(ColumnType::String, vec![CellValue::Text("1.5".into()), CellValue::Text("n/a".into())]);
+        assert!(matches!(agg(&text, AggregationType::Sum), Err(DmError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn min_and_max_skip_nan() {
+        let t = floats(&[f64::NAN, 1.0, 3.0]);
+        assert_eq!(agg(&t, AggregationType::Min).unwrap(), CellValue::Float(1.0));
+        assert_eq!(agg(&t, AggregationType::Max).unwrap(), CellValue::Float(3.0));
+        assert_eq!(agg(&floats(&[f64::NAN]), AggregationType::Max).unwrap(), CellValue::Null);
+    }
+
+    // Sums values and counts the merges it took
+    struct MergeCounter;
+
+    impl Aggregator for MergeCounter {
+        type State = (i64, i64);
+
+        fn name(&self) -> &str {
+            "merge_counter"
+        }
+        fn output_type(&self, _source: ColumnType) -> ColumnType {
+            ColumnType::String
+        }
+        fn init(&self) -> Self::State {
+            (0, 0)
+        }
+        fn update(&self, state: &mut Self::State, value: &CellValue, _table: &Table, _row: usize) -> Result<(), DmError> {
+            state.0 += value.as_f64().unwrap() as i64;
+            Ok(())
+        }
+        fn merge(&self, state: &mut Self::State, other: Self::State) {
+            state.0 += other.0;
+            state.1 += other.1 + 1;
+        }
+        fn finalize(&self, state: Self::State) -> CellValue {
+            CellValue::Text(format!("{} after {} merges", state.0, state.1))
+        }
+    }
+
+    #[test]
+    fn chunks_are_merged() {
+        let t = column(ColumnType::Int, (1..=6).map(CellValue::Int).chain([CellValue::Null]).collect());
+        let chunks: [&[usize]; 3] = [&[0, 1], &[2, 6], &[3, 4, 5]];
+        let custom = AggregationType::custom(MergeCounter);
+        assert_eq!(aggregate_row_chunks(&t, &chunks, 0, &custom).unwrap(), CellValue::Text("21 after 2 merges".into()));
+        assert_eq!(agg(&t, custom.clone()).unwrap(), CellValue::Text("21 after 0 merges".into()));
+        assert_eq!(aggregate_row_chunks(&t, &[], 0, &custom).unwrap(), CellValue::Text("0 after 0 merges".into()));
+
+        assert_eq!(aggregate_row_chunks(&t, &chunks, 0, &AggregationType::Sum).unwrap(), CellValue::Int(21));
+        assert_eq!(aggregate_row_chunks(&t, &chunks, 0, &AggregationType::Last).unwrap(), CellValue::Int(6));
+    }
+
+    #[test]
+    fn integer_sum_overflow_is_an_error() {
+        let t = column(ColumnType::Int, vec![CellValue::Int(i64::MAX), CellValue::Int(1)]);
+        assert!(matches!(agg(&t, AggregationType::Sum), Err(DmError::Overflow { .. })));
+    }
 }
@@ -1,12 +1,259 @@
 use std::collections::HashMap;
+use crate::cell_value::CellValue;
+use crate::columns::column_trait::{ColumnTrait, ColumnType};
+use crate::error::DmError;
+use crate::methods::aggregation::{aggregate_rows, AggregationType};
 use crate::table::Table;
 
-pub fn group_by(table: &Table, on: &str) -> HashMap<String, Vec<usize>> {
-    let idx = table.get_column_index(on).expect("Group column not found");
-    let mut map: HashMap<String, Vec<usize>> = HashMap::new();
+pub fn try_group_by(table: &Table, on: &str) -> Result<HashMap<CellValue, Vec<usize>>, DmError> {
+    let idx = table.try_get_column_index(on)?;
+    let mut map: HashMap<CellValue, Vec<usize>> = HashMap::new();
     for i in 0..table.len() {
-        let key = table.columns[idx].get_as_string(i);
+        let key = table.columns[idx].get(i);
         map.entry(key).or_default().push(i);
     }
-    map
+    Ok(map)
+}
+
+pub fn group_by(table: &Table, on: &str) -> HashMap<CellValue, Vec<usize>> {
+    try_group_by(table, on).unwrap_or_else(|e| panic!("{}", e))
+}
+
+impl Table {
+    /// Group the rows by one or more key columns. Aggregate the groups with
+    /// [`GroupBy::agg`], e.g. `table.group_by(&["region"]).agg([sum("sales").alias("total"), count()])`.
+    pub fn group_by(&self, keys: &[&str]) -> GroupBy<'_> {
+        GroupBy { table: self, keys: keys.iter().map(|k| k.to_string()).collect() }
+    }
+}
+
+/// Rows of a table grouped by key columns, created by [`Table::group_by`].
+pub struct GroupBy<'a> {
+    table: &'a Table,
+    keys: Vec<String>,
+}
+
+impl GroupBy<'_> {
+    /// Aggregate each group into one output row: the key columns, keeping their
+    /// types, then one column per aggregation. Groups appear in the order their
+    /// first row appears in the table, and null keys form a group of their own.
+    ///
+    /// Returns `DmError::ColumnNotFound` for a missing key or value column,
+    /// `DmError::DuplicateColumn` when two output columns share a name, and the
+    /// errors of [`aggregate_rows`] for values that cannot be aggregated.
+    pub fn try_agg(&self, aggs: impl IntoIterator<Item = Agg>) -> Result<Table, DmError> {
+        let table = self.table;
+        let key_idxs = self.keys.iter().map(|k| table.try_get_column_index(k)).collect::<Result<Vec<usize>, DmError>>()?;
+
+        // Groups in order of first appearance
+        let mut positions: HashMap<Vec<CellValue>, usize> = HashMap::new();
+        let mut groups: Vec<(Vec<CellValue>, Vec<usize>)> = Vec::new();
+        for row in 0..table.len() {
+            let key: Vec<CellValue> = key_idxs.iter().map(|&idx| table.columns[idx].get(row)).collect();
+            let pos = *positions.entry(key.clone()).or_insert_with(|| {
+                groups.push((key, Vec::new()));
+                groups.len() - 1
+            });
+            groups[pos].1.push(row);
+        }
+
+        let mut columns: Vec<Box<dyn ColumnTrait>> = Vec::new();
+        for (k, &idx) in key_idxs.iter().enumerate() {
+            let source = &table.columns[idx];
+            let mut col = source.get_type().new_column(source.name());
+            col.set_header(source.header().cloned());
+            for (key, _) in &groups {
+                col.try_push(key[k].clone())?;
+            }
+            columns.push(col);
+        }
+
+        for agg in aggs {
+            let name = agg.output_name();
+            let col = match &agg.column {
+                Some(column) => {
+                    let idx = table.try_get_column_index(column)?;
+                    let mut col = agg.agg_type.output_type(table.columns[idx].get_type()).new_column(&name);
+                    for (_, rows) in &groups {
+                        col.try_push(aggregate_rows(table, rows, idx, &agg.agg_type)?)?;
+                    }
+                    col
+                }
+                // Without a column, count the rows of each group
+                None => {
+                    let mut col = ColumnType::Int.new_column(&name);
+                    for (_, rows) in &groups {
+                        col.try_push(CellValue::Int(rows.len() as i64))?;
+                    }
+                    col
+                }
+            };
+            columns.push(col);
+        }
+
+        let mut out = Table::new();
+        for col in columns {
+            out.try_add_column(col)?;
+        }
+        Ok(out)
+    }
+
+    /// Aggregate each group, panicking on error. See [`GroupBy::try_agg`].
+    pub fn agg(&self, aggs: impl IntoIterator<Item = Agg>) -> Table {
+        self.try_agg(aggs).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+/// One aggregation of a [`GroupBy`], built with [`sum`], [`count`], [`mean`], ...
+/// The output column is named `<column>_<function>` (e.g. `sales_sum`, or
+/// `sales_quantile_0.9` for a quantile) unless renamed with [`Agg::alias`].
+#[derive(Debug, Clone)]
+pub struct Agg {
+    column: Option<String>,
+    agg_type: AggregationType,
+    alias: Option<String>,
+}
+
+impl Agg {
+    /// Aggregate `column` with any [`AggregationType`].
+    pub fn new(column: &str, agg_type: AggregationType) -> Self {
+        Self { column: Some(column.to_string()), agg_type, alias: None }
+    }
+
+    /// Name the output column.
+    pub fn alias(mut self, name: &str) -> Self {
+        self.alias = Some(name.to_string());
+        self
+    }
+
+    fn output_name(&self) -> String {
+        if let Some(alias) = &self.alias {
+            return alias.clone();
+        }
+        let quantile;
+        let function = match &self.agg_type {
+            AggregationType::Sum => "sum",
+            AggregationType::Count => "count",
+            AggregationType::Mean => "mean",
+            AggregationType::Min => "min",
+            AggregationType::Max => "max",
+            AggregationType::Median => "median",
+            AggregationType::Quantile(p) => {
+                quantile = format!("quantile_{}", p);
+                &quantile
+            }
+            AggregationType::StdDev => "std_dev",
+            AggregationType::Variance => "variance",
+            AggregationType::CountDistinct => "count_distinct",
+            AggregationType::First => "first",
+            AggregationType::Last => "last",
+            AggregationType::Custom(custom) => custom.name(),
+        };
+        match &self.column {
+            Some(column) => format!("{}_{}", column, function),
+            None => function.to_string(),
+        }
+    }
+}
+
+/// Number of rows in each group, nulls included, in a column named `count`.
+/// Use [`count_values`] to count the non-null values of a column.
+pub fn count() -> Agg {
+    Agg { column: None, agg_type: AggregationType::Count, alias: None }
+}
+
+pub fn count_values(column: &str) -> Agg {
+    Agg::new(column, AggregationType::Count)
+}
+
+pub fn sum(column: &str) -> Agg {
+    Agg::new(column, AggregationType::Sum)
+}
+
+pub fn mean(column: &str) -> Agg {
+    Agg::new(column, AggregationType::Mean)
+}
+
+pub fn min(column: &str) -> Agg {
+    Agg::new(column, AggregationType::Min)
+}
+
+pub fn max(column: &str) -> Agg {
+    Agg::new(column, AggregationType::Max)
+}
+
+pub fn median(column: &str) -> Agg {
+    Agg::new(column, AggregationType::Median)
+}
+
+pub fn quantile(column: &str, p: f64) -> Agg {
+    Agg::new(column, AggregationType::Quantile(p))
+}
+
+pub fn std_dev(column: &str) -> Agg {
+    Agg::new(column, AggregationType::StdDev)
+}
+
+pub fn variance(column: &str) -> Agg {
+    Agg::new(column, AggregationType::Variance)
+}
+
+pub fn count_distinct(column: &str) -> Agg {
+    Agg::new(column, AggregationType::CountDistinct)
+}
+
+pub fn first(column: &str) -> Agg {
+    Agg::new(column, AggregationType::First)
+}
+
+pub fn last(column: &str) -> Agg {
+    Agg::new(column, AggregationType::Last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::fixtures::{table, values};
+
+    fn sales() -> Table {
+        table(&[
+            ("day", ColumnType::DateTime, vec![CellValue::DateTime(86_400), CellValue::Null, CellValue::DateTime(0), CellValue::DateTime(86_400), CellValue::Null]),
+            ("store", ColumnType::Int, vec![CellValue::Int(2), CellValue::Int(1), CellValue::Int(1), CellValue::Int(2), CellValue::Int(1)]),
+            ("amount", ColumnType::Float, vec![CellValue::Float(1.0), CellValue::Null, CellValue::Float(3.0), CellValue::Float(4.0), CellValue::Float(5.0)]),
+        ])
+    }
+
+    fn types(t: &Table) -> Vec<ColumnType> {
+        t.columns.iter().map(|c| c.get_type()).collect()
+    }
+
+    #[test]
+    fn groups_keep_key_types_and_first_appearance_order() {
+        let out = sales().group_by(&["day", "store"]).agg([sum("amount")]);
+        assert_eq!(out.column_names(), ["day", "store", "amount_sum"]);
+        assert_eq!(types(&out), [ColumnType::DateTime, ColumnType::Int, ColumnType::Float]);
+        assert_eq!(values(&out, "day"), [CellValue::DateTime(86_400), CellValue::Null, CellValue::DateTime(0)]);
+        assert_eq!(values(&out, "store"), [CellValue::Int(2), CellValue::Int(1), CellValue::Int(1)]);
+        assert_eq!(values(&out, "amount_sum"), [CellValue::Float(5.0), CellValue::Float(5.0), CellValue::Float(3.0)]);
+    }
+
+    #[test]
+    fn count_includes_nulls_and_count_values_does_not() {
+        let out = sales().group_by(&["day"]).agg([count(), count_values("amount")]);
+        assert_eq!(out.column_names(), ["day", "count", "amount_count"]);
+        assert_eq!(values(&out, "count"), [CellValue::Int(2), CellValue::Int(2), CellValue::Int(1)]);
+        assert_eq!(values(&out, "amount_count"), [CellValue::Int(2), CellValue::Int(1), CellValue::Int(1)]);
+    }
+
+    #[test]
+    fn output_names_and_aliases() {
+        let out = sales().group_by(&["store"]).agg([quantile("amount", 0.1), quantile("amount", 0.9), max("amount").alias("top")]);
+        assert_eq!(out.column_names(), ["store", "amount_quantile_0.1", "amount_quantile_0.9", "top"]);
+
+        let clash = sales().group_by(&["store"]).try_agg([sum("amount"), sum("amount")]);
+        assert!(matches!(clash, Err(DmError::DuplicateColumn(name)) if name == "amount_sum"));
+        let clash = sales().group_by(&["store"]).try_agg([count().alias("store")]);
+        assert!(matches!(clash, Err(DmError::DuplicateColumn(name)) if name == "store"));
+        assert!(matches!(sales().group_by(&["shop"]).try_agg([count()]), Err(DmError::ColumnNotFound(_))));
+    }
 }
@@ -1,99 +1,1279 @@
-use std::collections::{HashMap, HashSet};
+use std::cmp::Ordering;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use crate::cell_value::CellValue;
+use crate::columns::column_trait::{ColumnTrait, ColumnType};
+use crate::error::DmError;
 use crate::table::Table;
-use crate::columns::string_column::StringColumn;
 
 pub enum JoinType {
+    /// Rows whose key exists in both tables
     Inner,
+    /// All left rows, with nulls where the right table has no match
     Left,
+    /// All right rows, with nulls where the left table has no match
     Right,
+    /// All rows from both tables, with nulls on the side without a match
+    Full,
+    /// Left rows that have at least one match, with left columns only
+    LeftSemi,
+    /// Left rows that have no match, with left columns only
+    LeftAnti,
+    /// Every left row paired with every right row; the key columns are ignored
+    Cross,
 }
 
-/// Perform a join on two tables based on key columns.
+/// How to name non-key columns that exist in both tables.
+pub enum Overlap {
+    /// Append the left and right suffixes to the names, like pandas `suffixes`
+    Suffixes(String, String),
+    /// Fail with `DmError::DuplicateColumn`
+    Error,
+}
+
+/// How rows are matched.
+pub enum JoinStrategy {
+    /// Sort-merge when both tables are already sorted by their keys, hash
+    /// otherwise. Checking the order costs a pass over both tables' keys, and
+    /// the row order of the output depends on which strategy runs
+    Auto,
+    /// Build a hash index on one side and probe it with the other. The default
+    Hash,
+    /// Stream both tables in key order; fails with `DmError::InvalidArgument`
+    /// unless both are sorted by their keys (see [`Table::is_sorted_by`])
+    SortMerge,
+}
+
+pub struct JoinOptions {
+    pub overlap: Overlap,
+    pub strategy: JoinStrategy,
+    /// Keep the right key columns as separate output columns instead of
+    /// merging them into the left ones
+    pub keep_right_keys: bool,
+    /// Expected relationship between the tables, checked on the matched rows;
+    /// `None` allows any
+    pub validate: Option<Cardinality>,
+    /// Name of an extra string column marking each output row as `left_only`,
+    /// `right_only` or `both`, like pandas `indicator`
+    pub indicator: Option<String>,
+}
+
+/// Expected relationship between the rows of a join, used to catch fan-out
+/// from duplicate keys. Violations fail with `DmError::Cardinality`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cardinality {
+    /// Every row matches at most one row of the other table
+    OneToOne,
+    /// Every right row matches at most one left row
+    OneToMany,
+    /// Every left row matches at most one right row
+    ManyToOne,
+}
+
+impl Cardinality {
+    fn name(&self) -> &'static str {
+        match self {
+            Cardinality::OneToOne => "one-to-one",
+            Cardinality::OneToMany => "one-to-many",
+            Cardinality::ManyToOne => "many-to-one",
+        }
+    }
+}
+
+impl Default for JoinOptions {
+    fn default() -> Self {
+        Self {
+            overlap: Overlap::Suffixes("_left".to_string(), "_right".to_string()),
+            strategy: JoinStrategy::Hash,
+            keep_right_keys: false,
+            validate: None,
+            indicator: None,
+        }
+    }
+}
+
+/// Perform a join on two tables based on key columns, with the default
+/// [`JoinOptions`]. See [`try_join_with`].
+pub fn try_join(left: &Table, right: &Table, on: &[&str], join_type: JoinType) -> Result<Table, DmError> {
+    try_join_with(left, right, on, join_type, &JoinOptions::default())
+}
+
+/// Perform a join on two tables based on key columns, panicking on error. See [`try_join`].
+pub fn join(left: &Table, right: &Table, on: &[&str], join_type: JoinType) -> Table {
+    try_join(left, right, on, join_type).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Perform a join on two tables based on key columns with the same names in
+/// both tables. See [`try_join_on`].
+pub fn try_join_with(left: &Table, right: &Table, on: &[&str], join_type: JoinType, options: &JoinOptions) -> Result<Table, DmError> {
+    try_join_on(left, right, on, on, join_type, options)
+}
+
+/// Perform a join on two tables with options, panicking on error. See [`try_join_with`].
+pub fn join_with(left: &Table, right: &Table, on: &[&str], join_type: JoinType, options: &JoinOptions) -> Table {
+    try_join_with(left, right, on, join_type, options).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Perform a join on two tables, matching `left_on` columns of the left table
+/// against `right_on` columns of the right table pairwise.
+///
+/// The output has the left columns in order followed by the right non-key
+/// columns, each keeping its source column type. Key columns appear once, under
+/// the left names, and take their value from whichever side matched; with
+/// `options.keep_right_keys` the right key columns are kept as well, and the
+/// left keys of right-only rows stay null. Non-key
+/// columns present in both tables are renamed or rejected according to
+/// `options.overlap`.
 ///
+/// Rows whose key contains a null never match. Columns from the side without
+/// a match are filled with nulls. With a hash strategy, rows come out in left
+/// table order (right table order for right joins), full joins appending the
+/// unmatched right rows at the end; a sort-merge join emits them in key order.
 ///
+/// `options.validate` rejects joins where a row matches more rows of the other
+/// table than expected, and `options.indicator` adds a column telling which
+/// side each output row came from.
 ///
 /// # Arguments
 ///
 /// * `left` - The left table
 /// * `right` - The right table
-/// * `on` - The column names on which to join
+/// * `left_on` - The left column names on which to join
+/// * `right_on` - The right column names on which to join, same arity as `left_on`
 /// * `join_type` - The type of join to perform
-pub fn join(left: &Table, right: &Table, on: &[&str], join_type: JoinType) -> Table {
+/// * `options` - Naming of overlapping columns, key handling, join strategy,
+///   validation and indicator column
+///
+/// A cross join ignores `left_on` and `right_on`.
+///
+/// Returns `DmError::ColumnNotFound` if a key column is missing,
+/// `DmError::LengthMismatch` if the key lists differ in length,
+/// `DmError::TypeMismatch` if paired key columns have different types and
+/// `DmError::Cardinality` if `options.validate` is violated.
+pub fn try_join_on(left: &Table, right: &Table, left_on: &[&str], right_on: &[&str], join_type: JoinType, options: &JoinOptions) -> Result<Table, DmError> {
+    // A cross join never looks at the keys, so they are not resolved
+    if matches!(join_type, JoinType::Cross) {
+        let pairs: Vec<RowPair> = (0..left.len())
+            .flat_map(|i| (0..right.len()).map(move |right_row| (Some(i), Some(right_row))))
+            .collect();
+        // Keys were not compared, so each side keeps its own key columns
+        return finish_join(left, right, (&[], &[]), &join_type, options, &pairs);
+    }
+
+    if left_on.len() != right_on.len() {
+        return Err(DmError::LengthMismatch { expected: left_on.len(), found: right_on.len() });
+    }
+
     // Get indexes
-    let left_on_idxs: Vec<usize> = on.iter().map(|&col| left.get_column_index(col).expect("Join column not found in left table")).collect();
-    let right_index = right.create_multi_index(on);
+    let left_on_idxs = left_on.iter().map(|&col| left.try_get_column_index(col)).collect::<Result<Vec<usize>, DmError>>()?;
+    let right_on_idxs = right_on.iter().map(|&col| right.try_get_column_index(col)).collect::<Result<Vec<usize>, DmError>>()?;
+    check_key_types(left, &left_on_idxs, right, &right_on_idxs)?;
+
+    let both_sorted = || -> Result<bool, DmError> { Ok(left.is_sorted_by(left_on)? && right.is_sorted_by(right_on)?) };
+    let pairs = match options.strategy {
+        JoinStrategy::Hash => hash_match(left, right, left_on, right_on, &join_type)?,
+        JoinStrategy::Auto if !both_sorted()? => hash_match(left, right, left_on, right_on, &join_type)?,
+        JoinStrategy::SortMerge if !both_sorted()? => {
+            return Err(DmError::InvalidArgument("sort-merge join needs both tables sorted by their keys".to_string()));
+        }
+        JoinStrategy::Auto | JoinStrategy::SortMerge => merge_match(left, right, (&left_on_idxs, &right_on_idxs), &join_type),
+    };
+
+    finish_join(left, right, (&left_on_idxs, &right_on_idxs), &join_type, options, &pairs)
+}
 
-    // Collect all columns from both tables
-    let mut all_columns: HashSet<String> = HashSet::new();
-    for col in left.columns.iter() {
-        all_columns.insert(col.name().to_string());
+/// Perform a join on differently named key columns, panicking on error. See [`try_join_on`].
+pub fn join_on(left: &Table, right: &Table, left_on: &[&str], right_on: &[&str], join_type: JoinType, options: &JoinOptions) -> Table {
+    try_join_on(left, right, left_on, right_on, join_type, options).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Which right row an as-of join picks for each left row.
+pub enum AsofDirection {
+    /// The latest right row at or before the left time
+    Backward,
+    /// The earliest right row at or after the left time
+    Forward,
+    /// The closest right row either way, preferring the earlier one on ties
+    Nearest,
+}
+
+pub struct AsofOptions {
+    pub direction: AsofDirection,
+    /// Maximum distance, in seconds, between the left and right times
+    pub tolerance: Option<i64>,
+    pub overlap: Overlap,
+}
+
+impl Default for AsofOptions {
+    fn default() -> Self {
+        Self {
+            direction: AsofDirection::Backward,
+            tolerance: None,
+            overlap: Overlap::Suffixes("_left".to_string(), "_right".to_string()),
+        }
     }
-    for col in right.columns.iter() {
-        all_columns.insert(col.name().to_string());
+}
+
+/// Match each left row to the nearest right row by time, e.g. the latest
+/// price at or before each trade.
+///
+/// Like a left join, every left row appears once, with nulls in the right
+/// columns when no right row qualifies. Rows are matched only within equal
+/// values of the `by` columns, which appear once in the output. The right time
+/// column is kept as a regular right column, so the matched time is visible.
+/// Null times and `by` keys never match.
+///
+/// # Arguments
+///
+/// * `left` - The left table
+/// * `right` - The right table, in any order
+/// * `left_on` - The left `DateTimeColumn` to match on
+/// * `right_on` - The right `DateTimeColumn` to match on
+/// * `by` - Columns, present in both tables, that must be equal
+/// * `options` - Direction, tolerance and naming of overlapping columns
+///
+/// Returns `DmError::TypeMismatch` if a time column is not a datetime column
+/// or a `by` column has different types in the two tables.
+pub fn try_join_asof(left: &Table, right: &Table, left_on: &str, right_on: &str, by: &[&str], options: &AsofOptions) -> Result<Table, DmError> {
+    let left_time = datetime_column_index(left, left_on)?;
+    let right_time = datetime_column_index(right, right_on)?;
+    let left_by = by.iter().map(|&col| left.try_get_column_index(col)).collect::<Result<Vec<usize>, DmError>>()?;
+    let right_by = by.iter().map(|&col| right.try_get_column_index(col)).collect::<Result<Vec<usize>, DmError>>()?;
+    check_key_types(left, &left_by, right, &right_by)?;
+
+    // Right rows of each `by` group, sorted by time
+    let mut groups: HashMap<Vec<CellValue>, Vec<(i64, usize)>> = HashMap::new();
+    for r in 0..right.len() {
+        let CellValue::DateTime(ts) = right.columns[right_time].get(r) else {
+            continue;
+        };
+        let key: Vec<CellValue> = right_by.iter().map(|&idx| right.columns[idx].get(r)).collect();
+        if !key.iter().any(CellValue::is_null) {
+            groups.entry(key).or_default().push((ts, r));
+        }
+    }
+    for rows in groups.values_mut() {
+        // stable, so equal times keep their table order
+        rows.sort_by_key(|&(ts, _)| ts);
     }
 
-    // Create a vector of column names preserving order (if needed)
-    let all_columns: Vec<String> = all_columns.into_iter().collect();
+    let mut pairs = Vec::with_capacity(left.len());
+    for l in 0..left.len() {
+        let key: Vec<CellValue> = left_by.iter().map(|&idx| left.columns[idx].get(l)).collect();
+        let matched = match (left.columns[left_time].get(l), groups.get(&key)) {
+            (CellValue::DateTime(ts), Some(rows)) => asof_pick(rows, ts, &options.direction, options.tolerance),
+            _ => None,
+        };
+        pairs.push((Some(l), matched));
+    }
 
-    // Create the result table with all columns, preserving types
-    let mut result = Table::new();
-    for col_name in &all_columns {
-        result.add_column(Box::new(StringColumn::new(col_name)));
+    build_output(left, right, (&left_by, &right_by), false, &options.overlap, false, &pairs)
+}
+
+/// Perform an as-of join, panicking on error. See [`try_join_asof`].
+pub fn join_asof(left: &Table, right: &Table, left_on: &str, right_on: &str, by: &[&str], options: &AsofOptions) -> Table {
+    try_join_asof(left, right, left_on, right_on, by, options).unwrap_or_else(|e| panic!("{}", e))
+}
+
+fn datetime_column_index(table: &Table, name: &str) -> Result<usize, DmError> {
+    let idx = table.try_get_column_index(name)?;
+    match table.columns[idx].get_type() {
+        ColumnType::DateTime => Ok(idx),
+        other => Err(DmError::TypeMismatch {
+            column: name.to_string(),
+            expected: ColumnType::DateTime,
+            found: format!("{:?}", other),
+        }),
+    }
+}
+
+// Pick the right row for time `ts` among `rows` sorted by time
+fn asof_pick(rows: &[(i64, usize)], ts: i64, direction: &AsofDirection, tolerance: Option<i64>) -> Option<usize> {
+    // the last of equal times is the latest row at or before `ts`
+    let backward = rows.partition_point(|&(t, _)| t <= ts).checked_sub(1).map(|p| rows[p]);
+    let forward = rows.get(rows.partition_point(|&(t, _)| t < ts)).copied();
+    let (t, row) = match direction {
+        AsofDirection::Backward => backward?,
+        AsofDirection::Forward => forward?,
+        AsofDirection::Nearest => match (backward, forward) {
+            (Some(b), Some(f)) if f.0.abs_diff(ts) < ts.abs_diff(b.0) => f,
+            (Some(b), _) => b,
+            (None, f) => f?,
+        },
+    };
+    match tolerance {
+        // a negative tolerance admits nothing
+        Some(tol) if u64::try_from(tol).map_or(true, |tol| t.abs_diff(ts) > tol) => None,
+        _ => Some(row),
+    }
+}
+
+/// A condition between a left column and right columns in [`try_join_where`].
+/// Column names are given left first.
+pub enum JoinPredicate<'a> {
+    /// `left = right`
+    Eq(&'a str, &'a str),
+    /// `left < right`
+    Lt(&'a str, &'a str),
+    /// `left <= right`
+    Le(&'a str, &'a str),
+    /// `left > right`
+    Gt(&'a str, &'a str),
+    /// `left >= right`
+    Ge(&'a str, &'a str),
+    /// `left BETWEEN right_start AND right_end`, bounds included
+    Between(&'a str, &'a str, &'a str),
+}
+
+// A range predicate with its columns resolved to indexes
+enum Range {
+    // `left <op> right`, with op given as the accepted orderings of left against right
+    Compare(usize, usize, [Ordering; 2]),
+    Between(usize, usize, usize),
+}
+
+/// Join two tables on equality and range predicates, all of which must hold,
+/// e.g. `left.ts BETWEEN right.start AND right.end` or `left.amount > right.threshold`.
+///
+/// Equality predicates partition both tables by hash. Within a partition, the
+/// first range predicate drives the matching: a `Between` sweeps left values in
+/// order against the intervals sorted by start, keeping only those still open,
+/// and a comparison binary searches the right rows sorted by that column. The
+/// remaining predicates are checked on the candidates, so no cross product is
+/// built. Range predicates compare ints and floats numerically, while equality
+/// predicates need columns of the same type, as in [`try_join_on`]; nulls
+/// never match.
+///
+/// Rows come out in left table order, right-only rows of right and full joins
+/// at the end. Columns of equality predicates are treated as join keys, as in
+/// [`try_join_on`].
+///
+/// Returns `DmError::InvalidArgument` when no predicate is given or for a cross
+/// join, and `DmError::TypeMismatch` when an equality predicate compares columns
+/// of different types or a range predicate compares unrelated types.
+pub fn try_join_where(left: &Table, right: &Table, predicates: &[JoinPredicate], join_type: JoinType, options: &JoinOptions) -> Result<Table, DmError> {
+    if predicates.is_empty() || matches!(join_type, JoinType::Cross) {
+        return Err(DmError::InvalidArgument("a predicate join needs at least one predicate and cannot be a cross join".to_string()));
     }
 
+    // Resolve a left column and a right column it is compared with
+    let resolve = |l: &str, r: &str, equality: bool| -> Result<(usize, usize), DmError> {
+        let (li, ri) = (left.try_get_column_index(l)?, right.try_get_column_index(r)?);
+        check_comparable(left, li, right, ri, equality)?;
+        Ok((li, ri))
+    };
+    let (mut left_keys, mut right_keys, mut ranges) = (Vec::new(), Vec::new(), Vec::new());
+    for predicate in predicates {
+        let range = match *predicate {
+            JoinPredicate::Eq(l, r) => {
+                let (li, ri) = resolve(l, r, true)?;
+                left_keys.push(li);
+                right_keys.push(ri);
+                continue;
+            }
+            JoinPredicate::Lt(l, r) | JoinPredicate::Le(l, r) | JoinPredicate::Gt(l, r) | JoinPredicate::Ge(l, r) => {
+                let (li, ri) = resolve(l, r, false)?;
+                let accepted = match predicate {
+                    JoinPredicate::Lt(..) => [Ordering::Less; 2],
+                    JoinPredicate::Le(..) => [Ordering::Less, Ordering::Equal],
+                    JoinPredicate::Gt(..) => [Ordering::Greater; 2],
+                    _ => [Ordering::Greater, Ordering::Equal],
+                };
+                Range::Compare(li, ri, accepted)
+            }
+            JoinPredicate::Between(l, start, end) => {
+                let (li, si) = resolve(l, start, false)?;
+                let (_, ei) = resolve(l, end, false)?;
+                Range::Between(li, si, ei)
+            }
+        };
+        ranges.push(range);
+    }
+
+    // Partition both sides by their equality keys
+    let partition = |table: &Table, keys: &[usize]| {
+        let mut parts: HashMap<Vec<CellValue>, Vec<usize>> = HashMap::new();
+        for row in 0..table.len() {
+            let key: Vec<CellValue> = keys.iter().map(|&idx| table.columns[idx].get(row)).collect();
+            if key.iter().all(comparable) {
+                parts.entry(key).or_default().push(row);
+            }
+        }
+        parts
+    };
+    let left_parts = partition(left, &left_keys);
+    let right_parts = partition(right, &right_keys);
+
+    let mut matches: Vec<Vec<usize>> = vec![Vec::new(); left.len()];
+    for (key, left_rows) in &left_parts {
+        if let Some(right_rows) = right_parts.get(key) {
+            match_range(left, right, left_rows, right_rows, &ranges, &mut matches);
+        }
+    }
+
+    // Turn the matches into output rows for the join type
+    let mut pairs = Vec::new();
+    let mut right_matched = vec![false; right.len()];
+    for (l, right_rows) in matches.iter_mut().enumerate() {
+        right_rows.sort_unstable();
+        match join_type {
+            JoinType::LeftSemi | JoinType::LeftAnti => {
+                if right_rows.is_empty() == matches!(join_type, JoinType::LeftAnti) {
+                    pairs.push((Some(l), None));
+                }
+            }
+            _ if right_rows.is_empty() => {
+                if matches!(join_type, JoinType::Left | JoinType::Full) {
+                    pairs.push((Some(l), None));
+                }
+            }
+            _ => {
+                for &r in right_rows.iter() {
+                    right_matched[r] = true;
+                    pairs.push((Some(l), Some(r)));
+                }
+            }
+        }
+    }
+    if matches!(join_type, JoinType::Right | JoinType::Full) {
+        pairs.extend((0..right.len()).filter(|&r| !right_matched[r]).map(|r| (None, Some(r))));
+    }
+
+    finish_join(left, right, (&left_keys, &right_keys), &join_type, options, &pairs)
+}
+
+/// Perform a predicate join, panicking on error. See [`try_join_where`].
+pub fn join_where(left: &Table, right: &Table, predicates: &[JoinPredicate], join_type: JoinType, options: &JoinOptions) -> Table {
+    try_join_where(left, right, predicates, join_type, options).unwrap_or_else(|e| panic!("{}", e))
+}
+
+// Keys of different types never compare equal, so such a join is a mistake
+fn check_key_types(left: &Table, left_keys: &[usize], right: &Table, right_keys: &[usize]) -> Result<(), DmError> {
+    left_keys.iter().zip(right_keys).try_for_each(|(&li, &ri)| check_comparable(left, li, right, ri, true))
+}
+
+// Equality partitions rows by hashing their values, where `Int(1)` and
+// `Float(1.0)` differ, so only range comparisons may mix ints and floats
+fn check_comparable(left: &Table, li: usize, right: &Table, ri: usize, equality: bool) -> Result<(), DmError> {
+    let (lt, rt) = (left.columns[li].get_type(), right.columns[ri].get_type());
+    let numeric = |t: ColumnType| matches!(t, ColumnType::Int | ColumnType::Float);
+    if lt == rt || (!equality && numeric(lt) && numeric(rt)) {
+        Ok(())
+    } else {
+        Err(DmError::TypeMismatch {
+            column: right.columns[ri].name().to_string(),
+            expected: lt,
+            found: format!("{:?}", rt),
+        })
+    }
+}
+
+// Whether a value can match at all: nulls and NaN compare to nothing, and
+// leaving them out makes `CellValue::compare` a total order on the rest
+fn comparable(v: &CellValue) -> bool {
+    !v.is_null() && !matches!(v, CellValue::Float(f) if f.is_nan())
+}
+
+// A comparable value ordered with `CellValue::compare`, for sorting and heaps
+#[derive(PartialEq, Eq)]
+struct Ordered(CellValue);
+
+impl PartialOrd for Ordered {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ordered {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.compare(&other.0).unwrap_or(Ordering::Equal)
+    }
+}
+
+// Whether a range condition holds for a pair of rows; `compare` gives `None`,
+// so false, for nulls and NaN
+fn holds(range: &Range, left: &Table, l: usize, right: &Table, r: usize) -> bool {
+    match *range {
+        Range::Compare(li, ri, accepted) => {
+            left.columns[li].get(l).compare(&right.columns[ri].get(r)).is_some_and(|o| accepted.contains(&o))
+        }
+        Range::Between(li, si, ei) => {
+            let v = left.columns[li].get(l);
+            v.compare(&right.columns[si].get(r)).is_some_and(|o| o.is_ge())
+                && v.compare(&right.columns[ei].get(r)).is_some_and(|o| o.is_le())
+        }
+    }
+}
+
+// Find the matches of `left_rows` among `right_rows` (one equality partition),
+// driven by the first range condition and filtered by the others
+fn match_range(left: &Table, right: &Table, left_rows: &[usize], right_rows: &[usize], ranges: &[Range], matches: &mut [Vec<usize>]) {
+    let Some((driver, rest)) = ranges.split_first() else {
+        for &l in left_rows {
+            matches[l].extend_from_slice(right_rows);
+        }
+        return;
+    };
+    let mut emit = |l: usize, r: usize| {
+        if rest.iter().all(|c| holds(c, left, l, right, r)) {
+            matches[l].push(r);
+        }
+    };
+
+    match *driver {
+        Range::Compare(li, ri, accepted) => {
+            let mut sorted: Vec<(Ordered, usize)> = right_rows.iter()
+                .map(|&r| (Ordered(right.columns[ri].get(r)), r))
+                .filter(|(v, _)| comparable(&v.0))
+                .collect();
+            sorted.sort();
+            for &l in left_rows {
+                let v = Ordered(left.columns[li].get(l));
+                if !comparable(&v.0) {
+                    continue;
+                }
+                // right values below, equal to and above `v`
+                let lower = sorted.partition_point(|(rv, _)| *rv < v);
+                let upper = sorted.partition_point(|(rv, _)| *rv <= v);
+                let range = match accepted {
+                    [Ordering::Less, Ordering::Less] => upper..sorted.len(),
+                    [Ordering::Less, _] => lower..sorted.len(),
+                    [Ordering::Greater, Ordering::Greater] => 0..lower,
+                    _ => 0..upper,
+                };
+                for (_, r) in &sorted[range] {
+                    emit(l, *r);
+                }
+            }
+        }
+        Range::Between(li, si, ei) => {
+            let mut intervals: Vec<(Ordered, Ordered, usize)> = right_rows.iter()
+                .map(|&r| (Ordered(right.columns[si].get(r)), Ordered(right.columns[ei].get(r)), r))
+                .filter(|(s, e, _)| comparable(&s.0) && comparable(&e.0))
+                .collect();
+            intervals.sort_by(|a, b| a.0.cmp(&b.0));
+            let mut points: Vec<(Ordered, usize)> = left_rows.iter()
+                .map(|&l| (Ordered(left.columns[li].get(l)), l))
+                .filter(|(v, _)| comparable(&v.0))
+                .collect();
+            points.sort();
+
+            // Intervals that have started, keyed by end so the closed ones pop first
+            let mut open: BinaryHeap<Reverse<(Ordered, usize)>> = BinaryHeap::new();
+            let mut intervals = intervals.into_iter().peekable();
+            for (v, l) in points {
+                while let Some((s, _, _)) = intervals.peek() {
+                    if *s > v {
+                        break;
+                    }
+                    let (_, e, r) = intervals.next().unwrap();
+                    open.push(Reverse((e, r)));
+                }
+                while open.peek().is_some_and(|Reverse((e, _))| *e < v) {
+                    open.pop();
+                }
+                for Reverse((_, r)) in open.iter() {
+                    emit(l, *r);
+                }
+            }
+        }
+    }
+}
+
+// An output row as (left row, right row), None standing for nulls on that side
+type RowPair = (Option<usize>, Option<usize>);
+
+// Match rows through a hash index. Right joins probe an index of the left table in right row order;
+// every other type probes an index of the right table in left row order, full joins
+// then appending the unmatched right rows in order. Null keys never match.
+fn hash_match(left: &Table, right: &Table, left_on: &[&str], right_on: &[&str], join_type: &JoinType) -> Result<Vec<RowPair>, DmError> {
+    let has_null_key = |key: &[CellValue]| key.iter().any(CellValue::is_null);
+    let mut pairs = Vec::new();
+
+    if matches!(join_type, JoinType::Right) {
+        let left_index = left.try_create_multi_index(left_on)?;
+        let right_on_idxs = right_on.iter().map(|&col| right.try_get_column_index(col)).collect::<Result<Vec<usize>, DmError>>()?;
+        for r in 0..right.len() {
+            let key: Vec<CellValue> = right_on_idxs.iter().map(|&idx| right.columns[idx].get(r)).collect();
+            match left_index.get(&key).filter(|_| !has_null_key(&key)) {
+                Some(left_rows) => pairs.extend(left_rows.iter().map(|&l| (Some(l), Some(r)))),
+                None => pairs.push((None, Some(r))),
+            }
+        }
+        return Ok(pairs);
+    }
+
+    let right_index = right.try_create_multi_index(right_on)?;
+    let left_on_idxs = left_on.iter().map(|&col| left.try_get_column_index(col)).collect::<Result<Vec<usize>, DmError>>()?;
+    let mut right_matched = vec![false; right.len()];
+
     // Iterate over the left table
     for i in 0..left.len() {
-        let key: Vec<String> = left_on_idxs.iter().map(|&idx| left.columns[idx].get_as_string(i)).collect();
+        let key: Vec<CellValue> = left_on_idxs.iter().map(|&idx| left.columns[idx].get(i)).collect();
         // Check if the key is present in the right table
-        if let Some(right_rows) = right_index.get(&key) {
+        let right_rows = right_index.get(&key).filter(|_| !has_null_key(&key));
+        match (join_type, right_rows) {
+            (JoinType::LeftSemi, Some(_)) | (JoinType::LeftAnti, None) => pairs.push((Some(i), None)),
+            (JoinType::LeftSemi, None) | (JoinType::LeftAnti, Some(_)) => {}
             // Create a row for each match
-            for right_row in right_rows {
-                let mut row = HashMap::new();
-                for col_name in &all_columns {
-                    if let Some(col_idx) = left.get_column_index(col_name) {
-                        row.insert(col_name.as_str(), left.columns[col_idx].get_as_string(i));
-                    } else if let Some(col_idx) = right.get_column_index(col_name) {
-                        row.insert(col_name.as_str(), right.columns[col_idx].get_as_string(*right_row));
-                    }
+            (_, Some(right_rows)) => {
+                for &right_row in right_rows {
+                    right_matched[right_row] = true;
+                    pairs.push((Some(i), Some(right_row)));
+                }
+            }
+            // For left and full joins, add row with left table data and nulls for right table data
+            (JoinType::Left | JoinType::Full, None) => pairs.push((Some(i), None)),
+            (_, None) => {}
+        }
+    }
+
+    if matches!(join_type, JoinType::Full) {
+        // Add rows from right table that do not have matches in left table
+        pairs.extend((0..right.len()).filter(|&r| !right_matched[r]).map(|r| (None, Some(r))));
+    }
+
+    Ok(pairs)
+}
+
+// Match rows of two tables sorted by their keys by walking both in step, without
+// building an index. Unmatched rows are emitted as they are passed, so output
+// follows key order. Null keys (sorted first) never match.
+fn merge_match(left: &Table, right: &Table, keys: (&[usize], &[usize]), join_type: &JoinType) -> Vec<RowPair> {
+    let (left_keys, right_keys) = keys;
+    let null_key = |table: &Table, idxs: &[usize], row: usize| idxs.iter().any(|&idx| table.columns[idx].is_null(row));
+    let compare = |table_a: &Table, idxs_a: &[usize], a: usize, table_b: &Table, idxs_b: &[usize], b: usize| {
+        idxs_a.iter().zip(idxs_b)
+            .map(|(&ca, &cb)| table_a.columns[ca].get(a).cmp(&table_b.columns[cb].get(b)))
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal)
+    };
+
+    let keep_left = matches!(join_type, JoinType::Left | JoinType::Full | JoinType::LeftAnti);
+    let keep_right = matches!(join_type, JoinType::Right | JoinType::Full);
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+
+    while i < left.len() || j < right.len() {
+        let order = if i == left.len() || (j < right.len() && null_key(right, right_keys, j)) {
+            Ordering::Greater
+        } else if j == right.len() || null_key(left, left_keys, i) {
+            Ordering::Less
+        } else {
+            compare(left, left_keys, i, right, right_keys, j)
+        };
+        match order {
+            Ordering::Less => {
+                if keep_left {
+                    pairs.push((Some(i), None));
                 }
-                result.push_row(&row);
-            }
-        } else if matches!(join_type, JoinType::Left) {
-            // For left join, add row with left table data and nulls for right table data
-            let mut row = HashMap::new();
-            for col_name in &all_columns {
-                if let Some(col_idx) = left.get_column_index(col_name) {
-                    row.insert(col_name.as_str(), left.columns[col_idx].get_as_string(i));
-                } else {
-                    row.insert(col_name.as_str(), String::new()); // Assuming empty string for nulls
+                i += 1;
+            }
+            Ordering::Greater => {
+                if keep_right {
+                    pairs.push((None, Some(j)));
                 }
+                j += 1;
+            }
+            Ordering::Equal => {
+                // Find the runs of rows sharing this key on both sides
+                let mut i_end = i + 1;
+                while i_end < left.len() && compare(left, left_keys, i, left, left_keys, i_end).is_eq() {
+                    i_end += 1;
+                }
+                let mut j_end = j + 1;
+                while j_end < right.len() && compare(right, right_keys, j, right, right_keys, j_end).is_eq() {
+                    j_end += 1;
+                }
+                match join_type {
+                    JoinType::LeftSemi => pairs.extend((i..i_end).map(|l| (Some(l), None))),
+                    JoinType::LeftAnti => {}
+                    JoinType::Right => pairs.extend((j..j_end).flat_map(|r| (i..i_end).map(move |l| (Some(l), Some(r))))),
+                    _ => pairs.extend((i..i_end).flat_map(|l| (j..j_end).map(move |r| (Some(l), Some(r))))),
+                }
+                i = i_end;
+                j = j_end;
             }
-            result.push_row(&row);
         }
     }
 
-    if matches!(join_type, JoinType::Right) {
-        // For right join, add rows from right table that do not have matches in left table
-        for (key, right_rows) in right_index.iter() {
-            let left_rows: Vec<_> = (0..left.len()).filter(|&i| {
-                let left_key: Vec<String> = left_on_idxs.iter().map(|&idx| left.columns[idx].get_as_string(i)).collect();
-                &left_key == key
-            }).collect();
-            if left_rows.is_empty() {
-                for right_row in right_rows {
-                    let mut row = HashMap::new();
-                    for col_name in &all_columns {
-                        if let Some(_col_idx) = left.get_column_index(col_name) {
-                            row.insert(col_name.as_str(), String::new()); // Assuming empty string for nulls
-                        } else if let Some(col_idx) = right.get_column_index(col_name) {
-                            row.insert(col_name.as_str(), right.columns[col_idx].get_as_string(*right_row));
+    pairs
+}
+
+// Where an output column takes its values from
+enum Source {
+    Left(usize),
+    Right(usize),
+    // A merged key column: the left value, or the right one for right-only rows
+    Key(usize, usize),
+}
+
+// Validate the matched pairs, build the output and add the indicator column
+fn finish_join(left: &Table, right: &Table, keys: (&[usize], &[usize]), join_type: &JoinType, options: &JoinOptions, pairs: &[RowPair]) -> Result<Table, DmError> {
+    if let Some(cardinality) = options.validate {
+        validate_pairs(left, right, keys, cardinality, pairs)?;
+    }
+
+    // Semi and anti joins only filter the left table
+    let left_only = matches!(join_type, JoinType::LeftSemi | JoinType::LeftAnti);
+    let mut result = build_output(left, right, keys, left_only, &options.overlap, options.keep_right_keys, pairs)?;
+
+    if let Some(name) = &options.indicator {
+        let mut col = ColumnType::String.new_column(name);
+        for pair in pairs {
+            let label = match pair {
+                // semi join rows always had a match
+                (Some(_), None) if matches!(join_type, JoinType::LeftSemi) => "both",
+                (Some(_), None) => "left_only",
+                (None, _) => "right_only",
+                (Some(_), Some(_)) => "both",
+            };
+            col.try_push(CellValue::Text(label.to_string()))?;
+        }
+        result.try_add_column(col)?;
+    }
+    Ok(result)
+}
+
+// Check that no row matches more rows of the other table than `cardinality` allows
+fn validate_pairs(left: &Table, right: &Table, keys: (&[usize], &[usize]), cardinality: Cardinality, pairs: &[RowPair]) -> Result<(), DmError> {
+    let mut left_matches = vec![0; left.len()];
+    let mut right_matches = vec![0; right.len()];
+    for pair in pairs {
+        if let (Some(l), Some(r)) = *pair {
+            left_matches[l] += 1;
+            right_matches[r] += 1;
+        }
+    }
+
+    let check = |table: &Table, key_idxs: &[usize], counts: &[usize], side: &str| -> Result<(), DmError> {
+        match counts.iter().position(|&n| n > 1) {
+            Some(row) => Err(DmError::Cardinality {
+                expected: cardinality.name().to_string(),
+                side: side.to_string(),
+                row,
+                key: key_idxs.iter().map(|&idx| table.columns[idx].get_as_string(row)).collect::<Vec<String>>().join(", "),
+                matches: counts[row],
+            }),
+            None => Ok(()),
+        }
+    };
+    if matches!(cardinality, Cardinality::OneToOne | Cardinality::ManyToOne) {
+        check(left, keys.0, &left_matches, "left")?;
+    }
+    if matches!(cardinality, Cardinality::OneToOne | Cardinality::OneToMany) {
+        check(right, keys.1, &right_matches, "right")?;
+    }
+    Ok(())
+}
+
+// Materialize (left row, right row) pairs into a table with typed, ordered columns
+// `keys` holds the (left, right) key column indexes, compared pairwise.
+fn build_output(left: &Table, right: &Table, keys: (&[usize], &[usize]), left_only: bool, overlap: &Overlap, keep_right_keys: bool, pairs: &[RowPair]) -> Result<Table, DmError> {
+    let (left_keys, right_keys) = keys;
+    let mut sources = Vec::new();
+    let mut result = Table::new();
+
+    // Right columns that make it to the output
+    let right_out: Vec<usize> = if left_only {
+        Vec::new()
+    } else {
+        (0..right.columns.len())
+            .filter(|ri| keep_right_keys || !right_keys.contains(ri))
+            .collect()
+    };
+    let in_right_out = |name: &str| right_out.iter().any(|&ri| right.columns[ri].name() == name);
+
+    // Name of a column, renamed if the other side outputs it too
+    let output_name = |name: &str, overlaps: bool, is_left: bool| -> Result<String, DmError> {
+        if !overlaps {
+            return Ok(name.to_string());
+        }
+        match overlap {
+            Overlap::Suffixes(left_suffix, right_suffix) => {
+                Ok(format!("{}{}", name, if is_left { left_suffix } else { right_suffix }))
+            }
+            Overlap::Error => Err(DmError::DuplicateColumn(name.to_string())),
+        }
+    };
+
+    // An empty column like `source`, keeping its header
+    let add_column = |result: &mut Table, name: &str, source: &dyn ColumnTrait| -> Result<(), DmError> {
+        let mut col = source.get_type().new_column(name);
+        col.set_header(source.header().cloned());
+        result.try_add_column(col)
+    };
+
+    for (li, col) in left.columns.iter().enumerate() {
+        match left_keys.iter().position(|&k| k == li) {
+            // Key columns keep the left name, merging in the right key unless it is kept
+            Some(p) if !left_only => {
+                add_column(&mut result, col.name(), col.as_ref())?;
+                sources.push(if keep_right_keys { Source::Left(li) } else { Source::Key(li, right_keys[p]) });
+            }
+            _ => {
+                add_column(&mut result, &output_name(col.name(), in_right_out(col.name()), true)?, col.as_ref())?;
+                sources.push(Source::Left(li));
+            }
+        }
+    }
+    for &ri in &right_out {
+        let col = &right.columns[ri];
+        let overlaps = left.get_column_index(col.name()).is_some();
+        add_column(&mut result, &output_name(col.name(), overlaps, false)?, col.as_ref())?;
+        sources.push(Source::Right(ri));
+    }
+
+    for &(left_row, right_row) in pairs {
+        for (out, source) in result.columns.iter_mut().zip(&sources) {
+            let val = match (source, left_row, right_row) {
+                (Source::Left(ci), Some(r), _) | (Source::Key(ci, _), Some(r), _) => left.columns[*ci].get(r),
+                (Source::Right(ci), _, Some(r)) | (Source::Key(_, ci), None, Some(r)) => right.columns[*ci].get(r),
+                _ => CellValue::Null,
+            };
+            out.try_push(val)?;
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::fixtures::{table, values};
+    use crate::table::ColumnHeader;
+
+    // Deterministic pseudo-random numbers in 0..n
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, n: u64) -> u64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (self.0 >> 33) % n
+        }
+    }
+
+    fn ints(values: &[Option<i64>]) -> Vec<CellValue> {
+        values.iter().map(|v| v.map_or(CellValue::Null, CellValue::Int)).collect()
+    }
+
+    fn rows(t: &Table) -> Vec<Vec<CellValue>> {
+        (0..t.len()).map(|i| t.columns.iter().map(|c| c.get(i)).collect()).collect()
+    }
+
+    fn sorted_rows(t: &Table) -> Vec<Vec<CellValue>> {
+        let mut rows = rows(t);
+        rows.sort();
+        rows
+    }
+
+    // A table sorted by `k`, with duplicate and null keys
+    fn random_sorted(rng: &mut Lcg, len: usize, side: &str) -> Table {
+        let mut keys: Vec<Option<i64>> = (0..len)
+            .map(|_| if rng.next(10) == 0 { None } else { Some(rng.next(8) as i64) })
+            .collect();
+        keys.sort();
+        let labels = (0..len).map(|i| CellValue::Text(format!("{}{}", side, i))).collect();
+        table(&[("k", ColumnType::Int, ints(&keys)), (side, ColumnType::String, labels)])
+    }
+
+    fn all_types() -> Vec<JoinType> {
+        vec![JoinType::Inner, JoinType::Left, JoinType::Right, JoinType::Full, JoinType::LeftSemi, JoinType::LeftAnti]
+    }
+
+    #[test]
+    fn hash_and_sort_merge_give_the_same_rows() {
+        let mut rng = Lcg(7);
+        for _ in 0..50 {
+            let (left_len, right_len) = (rng.next(12) as usize, rng.next(12) as usize);
+            let left = random_sorted(&mut rng, left_len, "l");
+            let right = random_sorted(&mut rng, right_len, "r");
+            // JoinType is not Clone, so each strategy gets its own list
+            for (hash_type, merge_type) in all_types().into_iter().zip(all_types()) {
+                let hash = join_with(&left, &right, &["k"], hash_type, &JoinOptions::default());
+                let merge_options = JoinOptions { strategy: JoinStrategy::SortMerge, ..JoinOptions::default() };
+                let merge = join_with(&left, &right, &["k"], merge_type, &merge_options);
+                assert_eq!(hash.column_names(), merge.column_names());
+                assert_eq!(sorted_rows(&hash), sorted_rows(&merge));
+            }
+        }
+    }
+
+    #[test]
+    fn sort_merge_rejects_unsorted_input() {
+        let left = table(&[("k", ColumnType::Int, ints(&[Some(2), Some(1)]))]);
+        let options = JoinOptions { strategy: JoinStrategy::SortMerge, ..JoinOptions::default() };
+        assert!(matches!(try_join_with(&left, &left, &["k"], JoinType::Inner, &options), Err(DmError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn default_full_join_order_does_not_depend_on_sortedness() {
+        let left = table(&[("k", ColumnType::Int, ints(&[Some(1), Some(3)]))]);
+        let right = table(&[("k", ColumnType::Int, ints(&[Some(2), Some(3)]))]);
+        let joined = join(&left, &right, &["k"], JoinType::Full);
+        assert_eq!(rows(&joined), vec![vec![CellValue::Int(1)], vec![CellValue::Int(3)], vec![CellValue::Int(2)]]);
+    }
+
+    #[test]
+    fn cross_join_ignores_key_columns() {
+        let left = table(&[("a", ColumnType::Int, ints(&[Some(1), Some(2)]))]);
+        let right = table(&[("b", ColumnType::Int, ints(&[Some(3), Some(4), Some(5)]))]);
+        let joined = try_join(&left, &right, &["missing"], JoinType::Cross).unwrap();
+        assert_eq!(joined.column_names(), ["a", "b"]);
+        assert_eq!(joined.len(), 6);
+    }
+
+    #[test]
+    fn mismatched_key_types_are_rejected() {
+        let left = table(&[("k", ColumnType::Int, ints(&[Some(1)]))]);
+        let right = table(&[("k", ColumnType::Float, vec![CellValue::Float(2.5)])]);
+        assert!(matches!(try_join(&left, &right, &["k"], JoinType::Full), Err(DmError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn kept_right_keys_leave_left_keys_null() {
+        let left = table(&[("pid", ColumnType::Int, ints(&[Some(1), Some(3)]))]);
+        let right = table(&[("product_id", ColumnType::Int, ints(&[Some(1), Some(2)]))]);
+        let options = JoinOptions { keep_right_keys: true, ..JoinOptions::default() };
+        let joined = join_on(&left, &right, &["pid"], &["product_id"], JoinType::Full, &options);
+        assert_eq!(rows(&joined), vec![
+            ints(&[Some(1), Some(1)]),
+            ints(&[Some(3), None]),
+            ints(&[None, Some(2)]),
+        ]);
+        // merged keys still take the right value
+        let merged = join_on(&left, &right, &["pid"], &["product_id"], JoinType::Full, &JoinOptions::default());
+        assert_eq!(rows(&merged), vec![ints(&[Some(1)]), ints(&[Some(3)]), ints(&[Some(2)])]);
+    }
+
+    fn times(values: &[Option<i64>]) -> Vec<CellValue> {
+        values.iter().map(|v| v.map_or(CellValue::Null, CellValue::DateTime)).collect()
+    }
+
+    // Right value matched by each left time
+    fn asof(left_times: &[Option<i64>], right: &Table, options: &AsofOptions) -> Vec<CellValue> {
+        let left = table(&[("t", ColumnType::DateTime, times(left_times))]);
+        let joined = join_asof(&left, right, "t", "rt", &[], options);
+        values(&joined, "v")
+    }
+
+    // Right rows out of time order, with a duplicate time
+    fn quotes() -> Table {
+        table(&[
+            ("rt", ColumnType::DateTime, times(&[Some(20), Some(10), Some(20), None])),
+            ("v", ColumnType::Int, ints(&[Some(2), Some(1), Some(3), Some(9)])),
+        ])
+    }
+
+    fn with(direction: AsofDirection, tolerance: Option<i64>) -> AsofOptions {
+        AsofOptions { direction, tolerance, ..AsofOptions::default() }
+    }
+
+    #[test]
+    fn asof_backward_takes_latest_at_or_before() {
+        let got = asof(&[Some(5), Some(10), Some(15), Some(20), Some(99), None], &quotes(), &AsofOptions::default());
+        // equal right times keep table order, so the last of them is the latest
+        assert_eq!(got, ints(&[None, Some(1), Some(1), Some(3), Some(3), None]));
+    }
+
+    #[test]
+    fn asof_forward_takes_earliest_at_or_after() {
+        let got = asof(&[Some(5), Some(10), Some(15), Some(20), Some(21)], &quotes(), &with(AsofDirection::Forward, None));
+        assert_eq!(got, ints(&[Some(1), Some(1), Some(2), Some(2), None]));
+    }
+
+    #[test]
+    fn asof_nearest_prefers_earlier_on_ties() {
+        let got = asof(&[Some(0), Some(14), Some(15), Some(16), Some(30)], &quotes(), &with(AsofDirection::Nearest, None));
+        assert_eq!(got, ints(&[Some(1), Some(1), Some(1), Some(2), Some(3)]));
+    }
+
+    #[test]
+    fn asof_tolerance_is_inclusive() {
+        let got = asof(&[Some(12), Some(13), Some(20)], &quotes(), &with(AsofDirection::Backward, Some(2)));
+        assert_eq!(got, ints(&[Some(1), None, Some(3)]));
+        let got = asof(&[Some(7), Some(8)], &quotes(), &with(AsofDirection::Forward, Some(2)));
+        assert_eq!(got, ints(&[None, Some(1)]));
+        let got = asof(&[Some(15)], &quotes(), &with(AsofDirection::Nearest, Some(4)));
+        assert_eq!(got, ints(&[None]));
+    }
+
+    #[test]
+    fn asof_matches_within_by_groups() {
+        let left = table(&[
+            ("t", ColumnType::DateTime, times(&[Some(10), Some(10), Some(10)])),
+            ("sym", ColumnType::String, vec![CellValue::Text("a".into()), CellValue::Text("b".into()), CellValue::Null]),
+        ]);
+        let right = table(&[
+            ("rt", ColumnType::DateTime, times(&[Some(1), Some(5)])),
+            ("sym", ColumnType::String, vec![CellValue::Text("b".into()), CellValue::Text("a".into())]),
+            ("v", ColumnType::Int, ints(&[Some(1), Some(2)])),
+        ]);
+        let joined = join_asof(&left, &right, "t", "rt", &["sym"], &AsofOptions::default());
+        assert_eq!(joined.column_names(), ["t", "sym", "rt", "v"]);
+        assert_eq!(values(&joined, "v"), ints(&[Some(2), Some(1), None]));
+    }
+
+    #[test]
+    fn asof_needs_datetime_columns() {
+        let left = table(&[("t", ColumnType::Int, ints(&[Some(1)]))]);
+        assert!(matches!(try_join_asof(&left, &quotes(), "t", "rt", &[], &AsofOptions::default()), Err(DmError::TypeMismatch { .. })));
+    }
+
+    fn maybe(rng: &mut Lcg, n: u64) -> CellValue {
+        if rng.next(8) == 0 { CellValue::Null } else { CellValue::Int(rng.next(n) as i64) }
+    }
+
+    // A float in 0.0..n in steps of 0.5, sometimes NaN
+    fn maybe_nan(rng: &mut Lcg, n: u64) -> CellValue {
+        if rng.next(6) == 0 { CellValue::Float(f64::NAN) } else { CellValue::Float(rng.next(2 * n) as f64 / 2.0) }
+    }
+
+    // (left id, right id) of each output row, null where a side is missing
+    fn id_pairs(t: &Table) -> Vec<(CellValue, CellValue)> {
+        let id = |name: &str, i: usize| t.try_get_column_index(name).map_or(CellValue::Null, |c| t.columns[c].get(i));
+        let mut pairs: Vec<_> = (0..t.len()).map(|i| (id("l", i), id("r", i))).collect();
+        pairs.sort();
+        pairs
+    }
+
+    #[test]
+    fn join_where_matches_filtered_cross_product() {
+        let mut rng = Lcg(11);
+        for _ in 0..30 {
+            let (left_len, right_len) = (rng.next(10) as usize, rng.next(10) as usize);
+            let left = table(&[
+                ("g", ColumnType::Int, (0..left_len).map(|_| maybe(&mut rng, 3)).collect()),
+                ("x", ColumnType::Int, (0..left_len).map(|_| maybe(&mut rng, 10)).collect()),
+                ("f", ColumnType::Float, (0..left_len).map(|_| maybe_nan(&mut rng, 5)).collect()),
+                ("l", ColumnType::Int, (0..left_len as i64).map(CellValue::Int).collect()),
+            ]);
+            let right = table(&[
+                ("h", ColumnType::Int, (0..right_len).map(|_| maybe(&mut rng, 3)).collect()),
+                ("lo", ColumnType::Int, (0..right_len).map(|_| maybe(&mut rng, 10)).collect()),
+                ("hi", ColumnType::Int, (0..right_len).map(|_| maybe(&mut rng, 10)).collect()),
+                ("y", ColumnType::Float, (0..right_len).map(|_| maybe_nan(&mut rng, 10)).collect()),
+                ("r", ColumnType::Int, (0..right_len as i64).map(CellValue::Int).collect()),
+            ]);
+            let cases: Vec<Vec<JoinPredicate>> = vec![
+                vec![JoinPredicate::Eq("g", "h")],
+                vec![JoinPredicate::Eq("g", "h"), JoinPredicate::Between("x", "lo", "hi")],
+                vec![JoinPredicate::Ge("x", "lo"), JoinPredicate::Lt("x", "hi")],
+                vec![JoinPredicate::Gt("x", "y"), JoinPredicate::Le("x", "hi"), JoinPredicate::Eq("g", "h")],
+                // NaN never matches, whichever predicate drives
+                vec![JoinPredicate::Lt("f", "y")],
+                vec![JoinPredicate::Between("f", "lo", "y")],
+                vec![JoinPredicate::Eq("f", "y")],
+                vec![JoinPredicate::Ge("x", "lo"), JoinPredicate::Between("f", "lo", "y")],
+            ];
+            for predicates in &cases {
+                let cell = |t: &Table, name: &str, i: usize| t.columns[t.try_get_column_index(name).unwrap()].get(i);
+                let holds = |l: usize, r: usize| {
+                    predicates.iter().all(|p| {
+                        let cmp = |a: &str, b: &str| cell(&left, a, l).compare(&cell(&right, b, r));
+                        match *p {
+                            JoinPredicate::Eq(a, b) => cmp(a, b) == Some(Ordering::Equal),
+                            JoinPredicate::Lt(a, b) => cmp(a, b) == Some(Ordering::Less),
+                            JoinPredicate::Le(a, b) => cmp(a, b).is_some_and(Ordering::is_le),
+                            JoinPredicate::Gt(a, b) => cmp(a, b) == Some(Ordering::Greater),
+                            JoinPredicate::Ge(a, b) => cmp(a, b).is_some_and(Ordering::is_ge),
+                            JoinPredicate::Between(a, s, e) => cmp(a, s).is_some_and(Ordering::is_ge) && cmp(a, e).is_some_and(Ordering::is_le),
                         }
-                    }
-                    result.push_row(&row);
+                    })
+                };
+                let id = |i: usize| CellValue::Int(i as i64);
+                let matched: Vec<(usize, usize)> = (0..left_len).flat_map(|l| (0..right_len).map(move |r| (l, r))).filter(|&(l, r)| holds(l, r)).collect();
+                let left_hit = |l: usize| matched.iter().any(|&(ml, _)| ml == l);
+                let right_hit = |r: usize| matched.iter().any(|&(_, mr)| mr == r);
+                let inner = matched.iter().map(|&(l, r)| (id(l), id(r)));
+                let left_only = (0..left_len).filter(|&l| !left_hit(l)).map(|l| (id(l), CellValue::Null));
+                let right_only = (0..right_len).filter(|&r| !right_hit(r)).map(|r| (CellValue::Null, id(r)));
+
+                let mut expected: Vec<Vec<(CellValue, CellValue)>> = vec![
+                    inner.clone().collect(),
+                    inner.clone().chain(left_only.clone()).collect(),
+                    inner.clone().chain(left_only.clone()).chain(right_only).collect(),
+                    (0..left_len).filter(|&l| left_hit(l)).map(|l| (id(l), CellValue::Null)).collect(),
+                    left_only.collect(),
+                ];
+                expected.iter_mut().for_each(|e| e.sort());
+                let types = [JoinType::Inner, JoinType::Left, JoinType::Full, JoinType::LeftSemi, JoinType::LeftAnti];
+                for (join_type, expected) in types.into_iter().zip(expected) {
+                    let joined = join_where(&left, &right, predicates, join_type, &JoinOptions::default());
+                    assert_eq!(id_pairs(&joined), expected);
                 }
             }
         }
     }
 
-    result
-}
\ No newline at end of file
+    #[test]
+    fn join_where_rejects_mixed_numeric_equality() {
+        let left = table(&[("a", ColumnType::Int, ints(&[Some(1)]))]);
+        let right = table(&[("b", ColumnType::Float, vec![CellValue::Float(1.0)])]);
+        let eq = try_join_where(&left, &right, &[JoinPredicate::Eq("a", "b")], JoinType::Inner, &JoinOptions::default());
+        assert!(matches!(eq, Err(DmError::TypeMismatch { .. })));
+        let le = join_where(&left, &right, &[JoinPredicate::Le("a", "b")], JoinType::Inner, &JoinOptions::default());
+        assert_eq!(le.len(), 1);
+    }
+
+    #[test]
+    fn column_headers_are_kept() {
+        let header = ColumnHeader { value_column: "v".to_string(), aggregation: "Sum".to_string(), key: vec![CellValue::Int(1)] };
+        let mut left = table(&[("k", ColumnType::Int, ints(&[Some(1)])), ("x", ColumnType::Int, ints(&[Some(2)]))]);
+        left.columns[1].set_header(Some(header.clone()));
+        let right = Table { columns: left.clone().columns };
+        let joined = join(&left, &right, &["k"], JoinType::Inner);
+        assert_eq!(joined.column_names(), ["k", "x_left", "x_right"]);
+        assert_eq!(joined.columns[0].header(), None);
+        assert_eq!(joined.columns[1].header(), Some(&header));
+        assert_eq!(joined.columns[2].header(), Some(&header));
+    }
+
+    // Orders keyed by `k`, with an amount and a date
+    fn orders() -> Table {
+        table(&[
+            ("when", ColumnType::DateTime, vec![CellValue::DateTime(0), CellValue::DateTime(60)]),
+            ("k", ColumnType::Int, ints(&[Some(1), Some(2)])),
+            ("amount", ColumnType::Float, vec![CellValue::Float(1.5), CellValue::Null]),
+        ])
+    }
+
+    // Products keyed by `k`, sharing the `amount` name with orders
+    fn products() -> Table {
+        table(&[
+            ("name", ColumnType::String, vec![CellValue::Text("a".into()), CellValue::Text("c".into())]),
+            ("k", ColumnType::Int, ints(&[Some(1), Some(3)])),
+            ("amount", ColumnType::Int, ints(&[Some(10), Some(30)])),
+        ])
+    }
+
+    fn types(t: &Table) -> Vec<ColumnType> {
+        t.columns.iter().map(|c| c.get_type()).collect()
+    }
+
+    #[test]
+    fn output_has_left_then_right_columns_with_their_types() {
+        let left = table(&[("when", ColumnType::DateTime, vec![CellValue::DateTime(0)]), ("k", ColumnType::Int, ints(&[Some(1)]))]);
+        let right = table(&[("name", ColumnType::String, vec![CellValue::Null]), ("k", ColumnType::Int, ints(&[Some(1)])), ("x", ColumnType::Float, vec![CellValue::Float(0.5)])]);
+        let joined = join(&left, &right, &["k"], JoinType::Full);
+        assert_eq!(joined.column_names(), ["when", "k", "name", "x"]);
+        assert_eq!(types(&joined), [ColumnType::DateTime, ColumnType::Int, ColumnType::String, ColumnType::Float]);
+        assert_eq!(rows(&joined), [[CellValue::DateTime(0), CellValue::Int(1), CellValue::Null, CellValue::Float(0.5)]]);
+
+        let semi = join(&left, &right, &["k"], JoinType::LeftSemi);
+        assert_eq!(semi.column_names(), ["when", "k"]);
+    }
+
+    #[test]
+    fn unmatched_rows_get_typed_nulls() {
+        let joined = join(&orders(), &products(), &["k"], JoinType::Full);
+        assert_eq!(types(&joined), [ColumnType::DateTime, ColumnType::Int, ColumnType::Float, ColumnType::String, ColumnType::Int]);
+        assert_eq!(values(&joined, "k"), ints(&[Some(1), Some(2), Some(3)]));
+        assert_eq!(values(&joined, "when"), [CellValue::DateTime(0), CellValue::DateTime(60), CellValue::Null]);
+        assert_eq!(values(&joined, "name"), [CellValue::Text("a".into()), CellValue::Null, CellValue::Text("c".into())]);
+    }
+
+    #[test]
+    fn overlapping_columns_get_suffixes() {
+        let joined = join(&orders(), &products(), &["k"], JoinType::Inner);
+        assert_eq!(joined.column_names(), ["when", "k", "amount_left", "name", "amount_right"]);
+        assert_eq!(types(&joined)[2..], [ColumnType::Float, ColumnType::String, ColumnType::Int]);
+
+        let options = JoinOptions { overlap: Overlap::Suffixes("_o".to_string(), String::new()), ..JoinOptions::default() };
+        let joined = join_with(&orders(), &products(), &["k"], JoinType::Inner, &options);
+        assert_eq!(joined.column_names(), ["when", "k", "amount_o", "name", "amount"]);
+
+        // kept right keys overlap the left ones too
+        let options = JoinOptions { keep_right_keys: true, ..JoinOptions::default() };
+        let joined = join_with(&orders(), &products(), &["k"], JoinType::Inner, &options);
+        assert_eq!(joined.column_names(), ["when", "k", "amount_left", "name", "k_right", "amount_right"]);
+    }
+
+    #[test]
+    fn overlap_error_rejects_shared_names() {
+        let options = JoinOptions { overlap: Overlap::Error, ..JoinOptions::default() };
+        let result = try_join_with(&orders(), &products(), &["k"], JoinType::Inner, &options);
+        assert!(matches!(result, Err(DmError::DuplicateColumn(name)) if name == "amount"));
+        // left-only joins output no right columns to clash with
+        assert!(try_join_with(&orders(), &products(), &["k"], JoinType::LeftAnti, &options).is_ok());
+    }
+
+    #[test]
+    fn asof_handles_extreme_times() {
+        let right = table(&[
+            ("rt", ColumnType::DateTime, times(&[Some(i64::MIN), Some(i64::MAX)])),
+            ("v", ColumnType::Int, ints(&[Some(1), Some(2)])),
+        ]);
+        let got = asof(&[Some(0), Some(i64::MAX), Some(i64::MIN)], &right, &with(AsofDirection::Nearest, Some(10)));
+        assert_eq!(got, ints(&[None, Some(2), Some(1)]));
+        let got = asof(&[Some(-1), Some(1)], &right, &with(AsofDirection::Nearest, None));
+        assert_eq!(got, ints(&[Some(1), Some(2)]));
+        assert_eq!(asof(&[Some(i64::MAX)], &right, &with(AsofDirection::Backward, Some(-1))), ints(&[None]));
+    }
+
+    #[test]
+    fn asof_by_columns_must_share_a_type() {
+        let left = table(&[("t", ColumnType::DateTime, times(&[Some(1)])), ("sym", ColumnType::String, vec![CellValue::Text("1".into())])]);
+        let right = table(&[("rt", ColumnType::DateTime, times(&[Some(1)])), ("sym", ColumnType::Int, ints(&[Some(1)]))]);
+        let result = try_join_asof(&left, &right, "t", "rt", &["sym"], &AsofOptions::default());
+        assert!(matches!(result, Err(DmError::TypeMismatch { column, .. }) if column == "sym"));
+    }
+
+    fn keyed(keys: &[Option<i64>]) -> Table {
+        table(&[("k", ColumnType::Int, ints(keys))])
+    }
+
+    fn validated(left: &Table, right: &Table, cardinality: Cardinality) -> Result<Table, DmError> {
+        let options = JoinOptions { validate: Some(cardinality), ..JoinOptions::default() };
+        try_join_with(left, right, &["k"], JoinType::Inner, &options)
+    }
+
+    #[test]
+    fn cardinality_accepts_and_rejects() {
+        let unique = keyed(&[Some(1), Some(2)]);
+        let repeated = keyed(&[Some(2), Some(1), Some(1)]);
+        for cardinality in [Cardinality::OneToOne, Cardinality::OneToMany, Cardinality::ManyToOne] {
+            assert!(validated(&unique, &unique, cardinality).is_ok());
+        }
+        // each left row matches one right row, but right row 0 matches two left rows
+        assert!(validated(&repeated, &unique, Cardinality::ManyToOne).is_ok());
+        assert!(validated(&unique, &repeated, Cardinality::OneToMany).is_ok());
+
+        match validated(&repeated, &unique, Cardinality::OneToMany) {
+            Err(DmError::Cardinality { expected, side, row, key, matches }) => {
+                assert_eq!((expected.as_str(), side.as_str(), row, key.as_str(), matches), ("one-to-many", "right", 0, "1", 2));
+            }
+            other => panic!("{:?}", other.map(|t| t.len())),
+        }
+        match validated(&unique, &repeated, Cardinality::OneToOne) {
+            Err(DmError::Cardinality { side, row, matches, .. }) => assert_eq!((side.as_str(), row, matches), ("left", 0, 2)),
+            other => panic!("{:?}", other.map(|t| t.len())),
+        }
+        // unmatched and null keys never count as matches
+        assert!(validated(&keyed(&[None, None, Some(3)]), &keyed(&[None, Some(3)]), Cardinality::OneToOne).is_ok());
+    }
+
+    #[test]
+    fn indicator_labels_each_row() {
+        let (left, right) = (keyed(&[Some(1), Some(2)]), keyed(&[Some(2), Some(3)]));
+        let options = JoinOptions { indicator: Some("_merge".to_string()), ..JoinOptions::default() };
+        let labels = |join_type| {
+            let joined = join_with(&left, &right, &["k"], join_type, &options);
+            assert_eq!(joined.column_names().last(), Some(&"_merge"));
+            values(&joined, "_merge").iter().map(CellValue::as_string).collect::<Vec<_>>()
+        };
+        assert_eq!(labels(JoinType::Full), ["left_only", "both", "right_only"]);
+        assert_eq!(labels(JoinType::Right), ["both", "right_only"]);
+        assert_eq!(labels(JoinType::LeftSemi), ["both"]);
+        assert_eq!(labels(JoinType::LeftAnti), ["left_only"]);
+    }
+
+    #[test]
+    fn indicator_name_must_be_free() {
+        let left = table(&[("k", ColumnType::Int, ints(&[Some(1)])), ("_merge", ColumnType::Int, ints(&[Some(0)]))]);
+        let options = JoinOptions { indicator: Some("_merge".to_string()), ..JoinOptions::default() };
+        let result = try_join_with(&left, &keyed(&[Some(1)]), &["k"], JoinType::Inner, &options);
+        assert!(matches!(result, Err(DmError::DuplicateColumn(name)) if name == "_merge"));
+    }
+}
@@ -0,0 +1,170 @@
+use crate::cell_value::CellValue;
+use crate::columns::column_trait::ColumnType;
+use crate::columns::datetime_column::format_datetime;
+use crate::error::DmError;
+use crate::table::Table;
+
+/// Options for [`try_melt`].
+#[derive(Debug, Clone)]
+pub struct MeltOptions {
+    /// Name of the output column holding the melted column names.
+    pub variable_name: String,
+    /// Name of the output column holding the melted values.
+    pub value_name: String,
+}
+
+impl Default for MeltOptions {
+    fn default() -> Self {
+        Self {
+            variable_name: "variable".to_string(),
+            value_name: "value".to_string(),
+        }
+    }
+}
+
+/// Unpivot a table from wide to long form, the inverse of a pivot.
+///
+/// # Arguments
+///
+/// * `table` - the original table
+/// * `id_vars` - columns repeated on every output row
+/// * `value_vars` - columns to melt; when empty, every column not in `id_vars`
+/// * `options` - names of the variable and value columns
+///
+/// # Returns
+///
+/// A table with the id columns, then a string column naming the melted column
+/// and a column with its value: one row per input row and melted column, all
+/// rows of the first melted column first. Nulls are kept.
+///
+/// The value column takes the melted columns' type when they all share one,
+/// `Float` when they mix ints and floats, and `String` otherwise.
+///
+/// Returns `DmError::ColumnNotFound` if a column is missing and
+/// `DmError::DuplicateColumn` if an output name is used twice.
+///
+/// # Example
+///
+/// Melting `sales_2023` and `sales_2024` with `id_vars` ["region"]:
+///
+/// | region | sales_2023 | sales_2024 |
+/// |--------|------------|------------|
+/// | North  | 10         | 12         |
+/// | South  | 5          | 7          |
+///
+/// gives:
+///
+/// | region | variable   | value |
+/// |--------|------------|-------|
+/// | North  | sales_2023 | 10    |
+/// | South  | sales_2023 | 5     |
+/// | North  | sales_2024 | 12    |
+/// | South  | sales_2024 | 7     |
+///
+pub fn try_melt(table: &Table, id_vars: &[&str], value_vars: &[&str], options: &MeltOptions) -> Result<Table, DmError> {
+    let id_idxs = id_vars.iter().map(|&col| table.try_get_column_index(col)).collect::<Result<Vec<usize>, DmError>>()?;
+    let value_idxs = if value_vars.is_empty() {
+        (0..table.columns.len()).filter(|i| !id_idxs.contains(i)).collect()
+    } else {
+        value_vars.iter().map(|&col| table.try_get_column_index(col)).collect::<Result<Vec<usize>, DmError>>()?
+    };
+
+    // Unify the melted column types
+    let value_type = value_idxs.iter()
+        .map(|&idx| table.columns[idx].get_type())
+        .reduce(|a, b| match (a, b) {
+            _ if a == b => a,
+            (ColumnType::Int, ColumnType::Float) | (ColumnType::Float, ColumnType::Int) => ColumnType::Float,
+            _ => ColumnType::String,
+        })
+        .unwrap_or(ColumnType::String);
+
+    let mut out = Table::new();
+    for &idx in &id_idxs {
+        let source = &table.columns[idx];
+        let mut col = source.get_type().new_column(source.name());
+        col.set_header(source.header().cloned());
+        for _ in &value_idxs {
+            for row in 0..table.len() {
+                col.try_push(source.get(row))?;
+            }
+        }
+        out.try_add_column(col)?;
+    }
+
+    let mut variable = ColumnType::String.new_column(&options.variable_name);
+    let mut value = value_type.new_column(&options.value_name);
+    for &idx in &value_idxs {
+        let source = &table.columns[idx];
+        for row in 0..table.len() {
+            variable.try_push(CellValue::Text(source.name().to_string()))?;
+            let val = match source.get(row) {
+                // keep datetimes readable when they end up among strings
+                CellValue::DateTime(ts) if value_type == ColumnType::String => CellValue::Text(format_datetime(ts)),
+                other => other,
+            };
+            value.try_push(val)?;
+        }
+    }
+    out.try_add_column(variable)?;
+    out.try_add_column(value)?;
+
+    Ok(out)
+}
+
+/// Unpivot a table from wide to long form, panicking on error. See [`try_melt`].
+pub fn melt(table: &Table, id_vars: &[&str], value_vars: &[&str], options: &MeltOptions) -> Table {
+    try_melt(table, id_vars, value_vars, options).unwrap_or_else(|e| panic!("{}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::fixtures::{table, values};
+
+    fn text(s: &str) -> CellValue {
+        CellValue::Text(s.to_string())
+    }
+
+    fn wide() -> Table {
+        table(&[
+            ("id", ColumnType::Int, vec![CellValue::Int(1), CellValue::Int(2)]),
+            ("a", ColumnType::Int, vec![CellValue::Int(10), CellValue::Null]),
+            ("b", ColumnType::Float, vec![CellValue::Float(0.5), CellValue::Float(1.5)]),
+            ("t", ColumnType::DateTime, vec![CellValue::DateTime(0), CellValue::Null]),
+        ])
+    }
+
+    #[test]
+    fn ints_and_floats_melt_to_floats_keeping_nulls() {
+        let out = melt(&wide(), &["id"], &["a", "b"], &MeltOptions::default());
+        assert_eq!(out.column_names(), ["id", "variable", "value"]);
+        assert_eq!(out.columns[2].get_type(), ColumnType::Float);
+        assert_eq!(values(&out, "id"), [1, 2, 1, 2].map(CellValue::Int));
+        assert_eq!(values(&out, "variable"), [text("a"), text("a"), text("b"), text("b")]);
+        assert_eq!(values(&out, "value"), [CellValue::Float(10.0), CellValue::Null, CellValue::Float(0.5), CellValue::Float(1.5)]);
+    }
+
+    #[test]
+    fn all_other_columns_melt_by_default() {
+        let options = MeltOptions { variable_name: "field".to_string(), value_name: "reading".to_string() };
+        let out = melt(&wide(), &["id"], &[], &options);
+        assert_eq!(out.column_names(), ["id", "field", "reading"]);
+        assert_eq!(out.len(), 6);
+        // mixing numbers and datetimes gives strings, the datetimes in ISO 8601
+        assert_eq!(out.columns[2].get_type(), ColumnType::String);
+        assert_eq!(values(&out, "reading")[4..], [text("1970-01-01T00:00:00Z"), CellValue::Null]);
+        assert_eq!(values(&out, "reading")[0], text("10"));
+    }
+
+    #[test]
+    fn output_names_must_not_clash_with_id_columns() {
+        let clash = |variable_name: &str, value_name: &str| {
+            let options = MeltOptions { variable_name: variable_name.to_string(), value_name: value_name.to_string() };
+            try_melt(&wide(), &["id"], &["a"], &options)
+        };
+        assert!(matches!(clash("id", "value"), Err(DmError::DuplicateColumn(name)) if name == "id"));
+        assert!(matches!(clash("variable", "id"), Err(DmError::DuplicateColumn(name)) if name == "id"));
+        assert!(matches!(try_melt(&wide(), &["id"], &["z"], &MeltOptions::default()), Err(DmError::ColumnNotFound(_))));
+    }
+}
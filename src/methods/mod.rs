@@ -1,4 +1,5 @@
 pub mod join;
 pub mod aggregation;
 pub mod group_by;
-pub mod pivot;
\ No newline at end of file
+pub mod pivot;
+pub mod melt;
\ No newline at end of file
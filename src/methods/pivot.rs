@@ -1,6 +1,10 @@
-use std::collections::HashMap;
-use crate::table::Table;
-use crate::methods::aggregation::AggregationType;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use crate::cell_value::CellValue;
+use crate::columns::column_trait::ColumnType;
+use crate::error::DmError;
+use crate::table::{ColumnHeader, Table};
+use crate::methods::aggregation::{aggregate_rows, AggregationType};
 
 /// Create a pivot table.
 /// 
@@ -14,7 +18,12 @@ use crate::methods::aggregation::AggregationType;
 ///
 /// # Returns
 ///
-/// A new `Table` that represents the pivoted data.
+/// A new `Table` that represents the pivoted data, or a `DmError` if a column
+/// is missing or a value cannot be aggregated.
+///
+/// Row-group columns keep the types of their source columns. Rows and columns
+/// are ordered by their typed key values, and column names join the column-key
+/// values with `_`.
 ///
 /// # Example
 ///
@@ -38,8 +47,19 @@ use crate::methods::aggregation::AggregationType;
 /// | region | A    | B    |
 /// |--------|------|------|
 /// | North  | 10   | 20   |
-/// | South  | 5    | 0    |
+/// | South  | 5    | null |
 ///
+pub fn try_pivot(
+    table: &Table,
+    row_groups: &[&str],
+    col_groups: &[&str],
+    value_col: &str,
+    agg_type: AggregationType,
+) -> Result<Table, DmError> {
+    try_pivot_multi(table, row_groups, col_groups, &[(value_col, agg_type)])
+}
+
+/// Create a pivot table, panicking on error. See [`try_pivot`].
 pub fn pivot(
     table: &Table,
     row_groups: &[&str],
@@ -47,122 +67,444 @@ pub fn pivot(
     value_col: &str,
     agg_type: AggregationType,
 ) -> Table {
-    let value_idx = table.get_column_index(value_col).expect("Value column not found");
+    try_pivot(table, row_groups, col_groups, value_col, agg_type).unwrap_or_else(|e| panic!("{}", e))
+}
 
-    // Get indexes for row and column grouping
-    let row_idxs: Vec<usize> = row_groups.iter().map(|&rg| table.get_column_index(rg).expect("Row group column not found")).collect();
-    let col_idxs: Vec<usize> = col_groups.iter().map(|&cg| table.get_column_index(cg).expect("Col group column not found")).collect();
-
-    // Helper to build keys
-    fn make_key(table: &Table, idxs: &[usize], row: usize) -> String {
-        let mut parts = Vec::new();
-        for &i in idxs {
-            parts.push(table.columns[i].get_as_string(row));
+/// Options for [`try_pivot_with`].
+#[derive(Debug, Clone)]
+pub struct PivotOptions {
+    /// Append a total row and, for each value pair, a total column. Totals are
+    /// aggregated over the underlying rows, so Mean or CountDistinct totals are
+    /// exact rather than sums of cells.
+    pub margins: bool,
+    /// Key of the total row and column.
+    pub margins_label: String,
+    /// Value of cells whose row and column keys never occur together, and of
+    /// the total cells of listed rows without data. A whole float fills an
+    /// integer column as an integer, e.g. `Float(0.0)` gives `Int(0)` for counts.
+    pub fill_value: CellValue,
+    /// Order of the output rows.
+    pub row_order: PivotOrder,
+    /// Order of the value columns within each value pair.
+    pub column_order: PivotOrder,
+}
+
+impl Default for PivotOptions {
+    fn default() -> Self {
+        Self {
+            margins: false,
+            margins_label: "All".to_string(),
+            fill_value: CellValue::Null,
+            row_order: PivotOrder::Sorted,
+            column_order: PivotOrder::Sorted,
+        }
+    }
+}
+
+/// How pivot row or column keys are ordered.
+#[derive(Debug, Clone)]
+pub enum PivotOrder {
+    /// Ascending by the typed key values, nulls first
+    Sorted,
+    /// Like `Sorted`, but digit runs in text compare as numbers, so "2" comes before "10"
+    Natural,
+    /// In the order the keys first appear in the table
+    FirstAppearance,
+    /// Exactly these keys, one value per grouping column, in this order. Keys
+    /// absent from the data give rows or columns of fill values, and keys not
+    /// listed are left out (though margins still count their rows).
+    Keys(Vec<Vec<CellValue>>),
+}
+
+/// Create a pivot table aggregating several (value column, aggregation) pairs
+/// in one pass, with the default [`PivotOptions`]. See [`try_pivot_with`].
+pub fn try_pivot_multi(
+    table: &Table,
+    row_groups: &[&str],
+    col_groups: &[&str],
+    values: &[(&str, AggregationType)],
+) -> Result<Table, DmError> {
+    try_pivot_with(table, row_groups, col_groups, values, &PivotOptions::default())
+}
+
+/// Create a pivot table over several value pairs, panicking on error. See [`try_pivot_multi`].
+pub fn pivot_multi(
+    table: &Table,
+    row_groups: &[&str],
+    col_groups: &[&str],
+    values: &[(&str, AggregationType)],
+) -> Table {
+    try_pivot_multi(table, row_groups, col_groups, values).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Create a pivot table aggregating several (value column, aggregation) pairs
+/// in one pass.
+///
+/// The output has the row-group columns, then for each pair in order one column
+/// per column key. With a single pair columns are named `{key}_{agg}` as in
+/// [`try_pivot`]; with several, `{key}_{value_col}_{agg}`, e.g. `A_sales_Sum`
+/// and `A_price_Mean`. Key parts are joined with `_` and nulls print empty, so
+/// distinct keys can give the same name, e.g. `("a_b", "c")` and `("a", "b_c")`;
+/// a name already taken gets a `_2`, `_3`, ... suffix. Each value column also
+/// carries a [`ColumnHeader`] holding its value column, aggregation and key
+/// parts, which the table printer shows as stacked header lines.
+///
+/// With `options.margins` each pair gets a last column keyed by
+/// `options.margins_label` (e.g. `All_Sum`) and, when there are row groups, a
+/// total row is appended, its first row-group column holding the label and the
+/// others null. That first column becomes a `StringColumn` if it is not one
+/// already.
+///
+/// Rows and columns are ordered by `options.row_order` and
+/// `options.column_order`, and combinations without rows hold
+/// `options.fill_value`.
+///
+/// Returns `DmError::InvalidArgument` when no pair is given or, with margins,
+/// when a row of the first row-group column already reads as the margins
+/// label, and `DmError::TypeMismatch` when the fill value does not fit a value column,
+/// e.g. a fractional float for an integer column.
+pub fn try_pivot_with(
+    table: &Table,
+    row_groups: &[&str],
+    col_groups: &[&str],
+    values: &[(&str, AggregationType)],
+    options: &PivotOptions,
+) -> Result<Table, DmError> {
+    if values.is_empty() {
+        return Err(DmError::InvalidArgument("a pivot needs at least one value column".to_string()));
+    }
+    let value_idxs = values.iter().map(|(vc, _)| table.try_get_column_index(vc)).collect::<Result<Vec<usize>, DmError>>()?;
+    let col_name = |label: &str, (value_col, agg_type): &(&str, AggregationType)| {
+        if values.len() == 1 {
+            format!("{}_{:?}", label, agg_type)
+        } else {
+            format!("{}_{}_{:?}", label, value_col, agg_type)
         }
-        parts.join("|") // Join with a delimiter to form a composite key
+    };
+
+    // Get indexes for row and column grouping
+    let row_idxs = row_groups.iter().map(|&rg| table.try_get_column_index(rg)).collect::<Result<Vec<usize>, DmError>>()?;
+    let col_idxs = col_groups.iter().map(|&cg| table.try_get_column_index(cg)).collect::<Result<Vec<usize>, DmError>>()?;
+
+    // Composite keys keep the typed values of each grouping column
+    fn make_key(table: &Table, idxs: &[usize], row: usize) -> Vec<CellValue> {
+        idxs.iter().map(|&i| table.columns[i].get(row)).collect()
     }
 
     // Data structure: row_key -> col_key -> Vec<row_index>
-    let mut groups: HashMap<String, HashMap<String, Vec<usize>>> = HashMap::new();
+    let mut groups: HashMap<Vec<CellValue>, Cells> = HashMap::new();
+    // Unique keys in order of first appearance
+    let mut row_key_list: Vec<Vec<CellValue>> = Vec::new();
+    let mut col_keys: Vec<Vec<CellValue>> = Vec::new();
+    let mut col_key_set = HashSet::new();
 
     for i in 0..table.len() {
         let rkey = make_key(table, &row_idxs, i);
         let ckey = make_key(table, &col_idxs, i);
 
-        groups.entry(rkey).or_default().entry(ckey).or_default().push(i);
+        if col_key_set.insert(ckey.clone()) {
+            col_keys.push(ckey.clone());
+        }
+        let cells = groups.entry(rkey).or_insert_with_key(|rkey| {
+            row_key_list.push(rkey.clone());
+            Cells::new()
+        });
+        cells.entry(ckey).or_default().push(i);
     }
 
-    // Extract all unique col keys to build column headers in output
-    let mut col_key_set = std::collections::HashSet::new();
-    for cmap in groups.values() {
-        for ckey in cmap.keys() {
-            col_key_set.insert(ckey.clone());
+    let row_key_list = order_keys(row_key_list, &options.row_order);
+    let col_keys = order_keys(col_keys, &options.column_order);
+
+    // Output rows: the row-group values and the source rows per column key
+    let no_rows = Cells::new();
+    let mut out_rows: Vec<(Vec<CellValue>, &Cells)> =
+        row_key_list.iter().map(|rk| (rk.clone(), groups.get(rk).unwrap_or(&no_rows))).collect();
+    let mut col_totals: Cells = HashMap::new();
+    if options.margins {
+        for cmap in groups.values() {
+            for (ckey, rows) in cmap {
+                col_totals.entry(ckey.clone()).or_default().extend(rows);
+            }
+        }
+        // keep row order so First and Last see the rows as in the table
+        col_totals.values_mut().for_each(|rows| rows.sort_unstable());
+        // without row groups the single row already holds the totals
+        if !row_groups.is_empty() {
+            if row_key_list.iter().any(|rk| rk[0].as_string() == options.margins_label) {
+                return Err(DmError::InvalidArgument(format!(
+                    "margins label {:?} is also a value of row group {}",
+                    options.margins_label, row_groups[0]
+                )));
+            }
+            let mut total_key = vec![CellValue::Null; row_groups.len()];
+            total_key[0] = CellValue::Text(options.margins_label.clone());
+            out_rows.push((total_key, &col_totals));
         }
     }
 
-    let mut col_keys: Vec<String> = col_key_set.into_iter().collect();
-    col_keys.sort();
-
     // Prepare output table:
-    // Row grouping columns first
-    // Then one column per col_key for the aggregated values
+    // Row grouping columns first, typed like their source columns
+    // Then one column per value pair and col_key for the aggregated values
     let mut out = Table::new();
 
-    // Add columns for row groups
-    for &rg in row_groups {
-        // For now, let's just use StringColumn for simplicity
-        out.add_string_column(rg); 
+    for (k, (&rg, &idx)) in row_groups.iter().zip(&row_idxs).enumerate() {
+        let col_type = if options.margins && k == 0 { ColumnType::String } else { table.columns[idx].get_type() };
+        let mut col = col_type.new_column(rg);
+        col.set_header(table.columns[idx].header().cloned());
+        for (rk, _) in &out_rows {
+            col.try_push(rk[k].clone())?;
+        }
+        out.try_add_column(col)?;
+    }
+
+    for (pair, &value_idx) in values.iter().zip(&value_idxs) {
+        let value_type = pair.1.output_type(table.columns[value_idx].get_type());
+        let fill = fill_for(value_type, &options.fill_value);
+        let header = |key: Vec<CellValue>| ColumnHeader {
+            value_column: pair.0.to_string(),
+            aggregation: format!("{:?}", pair.1),
+            key,
+        };
+        for ck in &col_keys {
+            let mut col = value_type.new_column(&unique_name(&out, col_name(&key_label(ck), pair)));
+            col.set_header(Some(header(ck.clone())));
+            for (_, row_map) in &out_rows {
+                match row_map.get(ck) {
+                    Some(ridxs) => col.try_push(aggregate_rows(table, ridxs, value_idx, &pair.1)?)?,
+                    // no rows for this combination
+                    None => col.try_push(fill.clone())?,
+                }
+            }
+            out.try_add_column(col)?;
+        }
+        if options.margins {
+            let mut col = value_type.new_column(&unique_name(&out, col_name(&options.margins_label, pair)));
+            let mut total_key = vec![CellValue::Null; col_groups.len().max(1)];
+            total_key[0] = CellValue::Text(options.margins_label.clone());
+            col.set_header(Some(header(total_key)));
+            for (_, row_map) in &out_rows {
+                // a listed row without data
+                if row_map.is_empty() {
+                    col.try_push(fill.clone())?;
+                    continue;
+                }
+                let mut ridxs: Vec<usize> = row_map.values().flatten().copied().collect();
+                ridxs.sort_unstable();
+                col.try_push(aggregate_rows(table, &ridxs, value_idx, &pair.1)?)?;
+            }
+            out.try_add_column(col)?;
+        }
     }
 
-    // Add columns for each unique col_key
-    for ck in &col_keys {
-        // name could be the col grouping keys combined plus the agg function name
-        let col_name = format!("{}_{:?}", ck, agg_type);
-        out.add_float_column(&col_name);
+    Ok(out)
+}
+
+/// Create a pivot table with options, panicking on error. See [`try_pivot_with`].
+pub fn pivot_with(
+    table: &Table,
+    row_groups: &[&str],
+    col_groups: &[&str],
+    values: &[(&str, AggregationType)],
+    options: &PivotOptions,
+) -> Table {
+    try_pivot_with(table, row_groups, col_groups, values, options).unwrap_or_else(|e| panic!("{}", e))
+}
+
+fn order_keys(mut keys: Vec<Vec<CellValue>>, order: &PivotOrder) -> Vec<Vec<CellValue>> {
+    match order {
+        PivotOrder::Sorted => keys.sort(),
+        PivotOrder::Natural => keys.sort_by(|a, b| {
+            a.iter().zip(b).map(|(x, y)| natural_cmp(x, y)).find(|o| o.is_ne()).unwrap_or(a.len().cmp(&b.len()))
+        }),
+        PivotOrder::FirstAppearance => {}
+        PivotOrder::Keys(expected) => return expected.clone(),
     }
+    keys
+}
 
-    // Perform aggregation and fill data into `out`.
-    // We'll also need to figure out the unique row keys sorted, so we have stable ordering.
-    let mut row_key_list: Vec<String> = groups.keys().cloned().collect();
-    row_key_list.sort();
+// Compare text so that runs of digits are ordered by their numeric value
+fn natural_cmp(a: &CellValue, b: &CellValue) -> Ordering {
+    let (CellValue::Text(a), CellValue::Text(b)) = (a, b) else {
+        return a.cmp(b);
+    };
+    let (mut a, mut b) = (a.as_str(), b.as_str());
+    loop {
+        let (Some(ca), Some(cb)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+        let ordering = if ca.is_ascii_digit() && cb.is_ascii_digit() {
+            let (da, ra) = a.split_at(a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len()));
+            let (db, rb) = b.split_at(b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len()));
+            (a, b) = (ra, rb);
+            let (da, db) = (da.trim_start_matches('0'), db.trim_start_matches('0'));
+            da.len().cmp(&db.len()).then_with(|| da.cmp(db))
+        } else {
+            (a, b) = (&a[ca.len_utf8()..], &b[cb.len_utf8()..]);
+            ca.cmp(&cb)
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+}
 
-    for rk in &row_key_list {
-        let row_map: &HashMap<String, Vec<usize>> = &groups[rk];
+// Source rows of a pivot row, by column key
+type Cells = HashMap<Vec<CellValue>, Vec<usize>>;
 
-        // Parse the row key back into the individual row-group values
-        let rg_values: Vec<&str> = rk.split('|').collect();
+// Column name part for a column key, its values joined with `_`
+fn key_label(key: &[CellValue]) -> String {
+    key.iter().map(CellValue::as_string).collect::<Vec<String>>().join("_")
+}
 
-        // We'll build a HashMap<&str, &str> for the row and its aggregated values
-        // Actually we know the schema, so let's insert row keys first as strings.
-        // Then we will insert the aggregated values (floats) after.
+// The fill value for a column of `col_type`: a whole float becomes an integer for
+// an integer column, other values are left for the column to accept or reject
+fn fill_for(col_type: ColumnType, fill: &CellValue) -> CellValue {
+    match *fill {
+        CellValue::Float(f) if col_type == ColumnType::Int && f.fract() == 0.0 && f.abs() < i64::MAX as f64 => CellValue::Int(f as i64),
+        _ => fill.clone(),
+    }
+}
 
-        // Make a map to fill in and then call push_row_str at the end.
-        // But we have float columns for the aggregated values, so we need them as strings.
-        let mut row_values: HashMap<String, String> = HashMap::new();
+// `name`, or the first of `name_2`, `name_3`, ... that the table does not have yet
+fn unique_name(table: &Table, name: String) -> String {
+    let taken = |n: &str| table.try_get_column_index(n).is_ok();
+    if !taken(&name) {
+        return name;
+    }
+    (2..).map(|i| format!("{}_{}", name, i)).find(|n| !taken(n)).unwrap()
+}
 
-        // Insert row group values
-        for (rg_col, rg_val) in row_groups.iter().zip(rg_values.iter()) {
-            row_values.insert(rg_col.to_string(), rg_val.to_string());
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::fixtures::{table, values};
 
-        // For each col key, aggregate
-        for ck in &col_keys {
-            let rows_for_ck: Option<&Vec<usize>> = row_map.get(ck);
-            let agg_result: f64 = if let Some(ridxs) = rows_for_ck {
-                aggregate_rows(table, ridxs, value_idx, agg_type)
-            } else {
-                // no rows for this combination
-                0.0
-            };
-
-            // Identify the output column name
-            let col_name: String = format!("{}_{:?}", ck, agg_type);
-            row_values.insert(col_name, agg_result.to_string());
-        }
-        
-        row_values.insert("row_key".to_string(), rk.clone());
+    fn text(s: &str) -> CellValue {
+        CellValue::Text(s.to_string())
+    }
 
-        // Create a temporary HashMap<&str, String>
-        let temp_row_values: HashMap<&str, String> = row_values.iter().map(|(k, v)| (k.as_str(), v.clone())).collect();
+    fn header<'a>(t: &'a Table, name: &str) -> &'a ColumnHeader {
+        t.columns[t.try_get_column_index(name).unwrap()].header().unwrap()
+    }
 
-        out.push_row(&temp_row_values);
+    #[test]
+    fn colliding_key_labels_get_a_suffix() {
+        let t = table(&[
+            ("r", ColumnType::Int, vec![CellValue::Int(1); 4]),
+            ("c1", ColumnType::String, vec![text("a_b"), text("a"), CellValue::Null, text("")]),
+            ("c2", ColumnType::String, vec![text("c"), text("b_c"), text("x"), text("x")]),
+            ("v", ColumnType::Int, (1..=4).map(CellValue::Int).collect()),
+        ]);
+        let options = PivotOptions { column_order: PivotOrder::FirstAppearance, ..PivotOptions::default() };
+        let out = pivot_with(&t, &["r"], &["c1", "c2"], &[("v", AggregationType::Sum)], &options);
+        assert_eq!(out.column_names(), ["r", "a_b_c_Sum", "a_b_c_Sum_2", "_x_Sum", "_x_Sum_2"]);
+        assert_eq!(values(&out, "a_b_c_Sum_2"), [CellValue::Int(2)]);
+        assert_eq!(values(&out, "_x_Sum_2"), [CellValue::Int(4)]);
+        assert_eq!(header(&out, "_x_Sum").key, [CellValue::Null, text("x")]);
+        assert_eq!(header(&out, "_x_Sum_2").key, [text(""), text("x")]);
     }
 
-    out
-}
+    #[test]
+    fn margin_label_colliding_with_a_key_gets_a_suffix() {
+        let t = table(&[
+            ("r", ColumnType::Int, vec![CellValue::Int(1), CellValue::Int(2)]),
+            ("c", ColumnType::String, vec![text("All"), text("b")]),
+            ("v", ColumnType::Int, vec![CellValue::Int(1), CellValue::Int(2)]),
+        ]);
+        let options = PivotOptions { margins: true, ..PivotOptions::default() };
+        let out = pivot_with(&t, &["r"], &["c"], &[("v", AggregationType::Sum)], &options);
+        assert_eq!(out.column_names(), ["r", "All_Sum", "b_Sum", "All_Sum_2"]);
+        assert_eq!(values(&out, "All_Sum_2"), [CellValue::Int(1), CellValue::Int(2), CellValue::Int(3)]);
+    }
 
-/// Helper function to aggregate a set of rows from the value column using a specific aggregation.
-fn aggregate_rows(table: &Table, rows: &[usize], value_idx: usize, agg_type: AggregationType) -> f64 {
-    match agg_type {
-        AggregationType::Count => rows.len() as f64,
-        AggregationType::Sum => {
-            let mut sum = 0.0;
-            for &r in rows {
-                let val_str = table.columns[value_idx].get_as_string(r);
-                let val: f64 = val_str.parse().expect("Non-numeric value in sum");
-                sum += val;
-            }
-            sum
-        }
+    // Sales by region and month, the months out of natural order
+    fn sales() -> Table {
+        table(&[
+            ("region", ColumnType::String, vec![text("b"), text("a"), text("b"), text("a"), text("b")]),
+            ("month", ColumnType::String, vec![text("m10"), text("m2"), text("m2"), text("m2"), text("m1")]),
+            ("v", ColumnType::Int, [1, 2, 3, 4, 10].into_iter().map(CellValue::Int).collect()),
+        ])
+    }
+
+    fn names(t: &Table) -> Vec<&str> {
+        t.column_names()
+    }
+
+    #[test]
+    fn column_orders() {
+        let order = |column_order| {
+            let options = PivotOptions { column_order, ..PivotOptions::default() };
+            pivot_with(&sales(), &["region"], &["month"], &[("v", AggregationType::Sum)], &options)
+        };
+        assert_eq!(names(&order(PivotOrder::Sorted)), ["region", "m1_Sum", "m10_Sum", "m2_Sum"]);
+        assert_eq!(names(&order(PivotOrder::Natural)), ["region", "m1_Sum", "m2_Sum", "m10_Sum"]);
+        assert_eq!(names(&order(PivotOrder::FirstAppearance)), ["region", "m10_Sum", "m2_Sum", "m1_Sum"]);
+        let keys = PivotOrder::Keys(vec![vec![text("m2")], vec![text("m9")]]);
+        assert_eq!(names(&order(keys)), ["region", "m2_Sum", "m9_Sum"]);
+    }
+
+    #[test]
+    fn margins_aggregate_the_underlying_rows() {
+        let options = PivotOptions { margins: true, ..PivotOptions::default() };
+        let out = pivot_with(&sales(), &["region"], &["month"], &[("v", AggregationType::Mean)], &options);
+        assert_eq!(values(&out, "region"), [text("a"), text("b"), text("All")]);
+        // the grand total is the mean of all five rows, not of the row means
+        assert_eq!(values(&out, "All_Mean"), [CellValue::Float(3.0), CellValue::Float(14.0 / 3.0), CellValue::Float(4.0)]);
+        assert_eq!(values(&out, "m2_Mean"), [CellValue::Float(3.0), CellValue::Float(3.0), CellValue::Float(3.0)]);
+
+        // First sees the rows in table order
+        let out = pivot_with(&sales(), &["region"], &["month"], &[("v", AggregationType::First)], &options);
+        assert_eq!(values(&out, "All_First"), [CellValue::Int(2), CellValue::Int(1), CellValue::Int(1)]);
+    }
+
+    #[test]
+    fn margins_without_row_groups_add_no_total_row() {
+        let options = PivotOptions { margins: true, ..PivotOptions::default() };
+        let out = pivot_with(&sales(), &[], &["month"], &[("v", AggregationType::Sum)], &options);
+        assert_eq!(out.len(), 1);
+        assert_eq!(names(&out), ["m1_Sum", "m10_Sum", "m2_Sum", "All_Sum"]);
+        assert_eq!(values(&out, "All_Sum"), [CellValue::Int(20)]);
+    }
+
+    #[test]
+    fn margins_label_must_not_be_a_row_key() {
+        let options = PivotOptions { margins: true, margins_label: "a".to_string(), ..PivotOptions::default() };
+        let out = try_pivot_with(&sales(), &["region"], &["month"], &[("v", AggregationType::Sum)], &options);
+        assert!(matches!(out, Err(DmError::InvalidArgument(_))));
+
+        // keys that are not text are compared as they will print
+        let t = table(&[
+            ("r", ColumnType::Int, vec![CellValue::Int(1), CellValue::Int(2)]),
+            ("c", ColumnType::String, vec![text("x"), text("x")]),
+            ("v", ColumnType::Int, vec![CellValue::Int(1), CellValue::Int(2)]),
+        ]);
+        let options = PivotOptions { margins: true, margins_label: "2".to_string(), ..PivotOptions::default() };
+        assert!(matches!(try_pivot_with(&t, &["r"], &["c"], &[("v", AggregationType::Sum)], &options), Err(DmError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn listed_rows_without_data_are_filled() {
+        let options = PivotOptions {
+            margins: true,
+            fill_value: CellValue::Float(0.0),
+            row_order: PivotOrder::Keys(vec![vec![text("z")], vec![text("a")]]),
+            ..PivotOptions::default()
+        };
+        let out = pivot_with(&sales(), &["region"], &["month"], &[("v", AggregationType::Count)], &options);
+        assert_eq!(values(&out, "region"), [text("z"), text("a"), text("All")]);
+        assert_eq!(values(&out, "m1_Count"), [CellValue::Int(0), CellValue::Int(0), CellValue::Int(1)]);
+        // unlisted row b still counts in the totals
+        assert_eq!(values(&out, "All_Count"), [CellValue::Int(0), CellValue::Int(2), CellValue::Int(5)]);
+    }
+
+    #[test]
+    fn fill_value_must_fit_the_column() {
+        let fill = |fill_value| {
+            let options = PivotOptions { fill_value, ..PivotOptions::default() };
+            try_pivot_with(&sales(), &["region"], &["month"], &[("v", AggregationType::Sum)], &options)
+        };
+        assert_eq!(values(&fill(CellValue::Int(-1)).unwrap(), "m1_Sum"), [CellValue::Int(-1), CellValue::Int(10)]);
+        assert_eq!(values(&fill(CellValue::Float(-1.0)).unwrap(), "m1_Sum"), [CellValue::Int(-1), CellValue::Int(10)]);
+        assert!(matches!(fill(CellValue::Float(0.5)), Err(DmError::TypeMismatch { .. })));
     }
 }
@@ -1,36 +1,72 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
-use crate::columns::column_trait::ColumnTrait;
+use crate::cell_value::CellValue;
+use crate::columns::column_trait::{ColumnTrait, ColumnType};
+pub use crate::columns::column_trait::ColumnHeader;
 use crate::columns::string_column::StringColumn;
 use crate::columns::float_column::FloatColumn;
-use crate::columns::column_trait::ColumnType;
+use crate::error::DmError;
 
 pub struct Table {
     pub columns: Vec<Box<dyn ColumnTrait>>,
 }
 
+impl Default for Table {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Table {
     pub fn new() -> Self {
         Self { columns: Vec::new() }
     }
 
-    pub fn add_column(&mut self, col: Box<dyn ColumnTrait>) {
+    pub fn try_add_column(&mut self, col: Box<dyn ColumnTrait>) -> Result<(), DmError> {
         // Ensure unique name
-        let name = col.name().to_string();
-        if self.columns.iter().any(|c| c.name() == name) {
-            panic!("Column with name {} already exists", name);
+        if self.get_column_index(col.name()).is_some() {
+            return Err(DmError::DuplicateColumn(col.name().to_string()));
+        }
+        // Ensure the new column lines up with the existing rows
+        if !self.columns.is_empty() && col.len() != self.len() {
+            return Err(DmError::LengthMismatch { expected: self.len(), found: col.len() });
         }
         self.columns.push(col);
+        Ok(())
+    }
+
+    pub fn add_column(&mut self, col: Box<dyn ColumnTrait>) {
+        if let Err(e) = self.try_add_column(col) {
+            panic!("{}", e);
+        }
+    }
+
+    pub fn try_add_string_column(&mut self, name: &str) -> Result<(), DmError> {
+        self.try_add_column(Box::new(StringColumn::new(name)))
     }
 
     pub fn add_string_column(&mut self, name: &str) {
         self.add_column(Box::new(StringColumn::new(name)));
     }
 
+    pub fn try_add_float_column(&mut self, name: &str) -> Result<(), DmError> {
+        self.try_add_column(Box::new(FloatColumn::new(name)))
+    }
+
     pub fn add_float_column(&mut self, name: &str) {
         self.add_column(Box::new(FloatColumn::new(name)));
     }
 
+    pub fn try_add_typed_column(&mut self, name: &str, col_type: ColumnType) -> Result<(), DmError> {
+        self.try_add_column(col_type.new_column(name))
+    }
+
+    pub fn add_typed_column(&mut self, name: &str, col_type: ColumnType) {
+        if let Err(e) = self.try_add_typed_column(name, col_type) {
+            panic!("{}", e);
+        }
+    }
+
     pub fn column_names(&self) -> Vec<&str> {
         self.columns.iter().map(|c| c.name()).collect()
     }
@@ -39,6 +75,10 @@ impl Table {
         self.columns.iter().position(|c| c.name() == name)
     }
 
+    pub fn try_get_column_index(&self, name: &str) -> Result<usize, DmError> {
+        self.get_column_index(name).ok_or_else(|| DmError::ColumnNotFound(name.to_string()))
+    }
+
     pub fn len(&self) -> usize {
         if self.columns.is_empty() {
             0
@@ -47,54 +87,118 @@ impl Table {
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn get_rows(&self) -> Vec<HashMap<&str, String>> {
         let mut rows = Vec::new();
         for i in 0..self.len() {
             let mut row = HashMap::new();
             for c in &self.columns {
-                row.insert(c.name(), c.get_as_string(i));
+                // null cells are left out so the row round-trips through `push_row`
+                if !c.is_null(i) {
+                    row.insert(c.name(), c.get_as_string(i));
+                }
             }
             rows.push(row);
         }
         rows
     }
 
-    pub fn push_row(&mut self, values: &HashMap<&str, String>) {
+    pub fn get_rows_typed(&self) -> Vec<HashMap<&str, CellValue>> {
+        (0..self.len())
+            .map(|i| self.columns.iter().map(|c| (c.name(), c.get(i))).collect())
+            .collect()
+    }
+
+    pub fn try_push_row_typed(&mut self, values: &HashMap<&str, CellValue>) -> Result<(), DmError> {
+        // columns missing from `values` receive a null
+        let len = self.len();
+        let pushed = self.columns.iter_mut().try_for_each(|c| match values.get(c.name()) {
+            Some(val) => c.try_push(val.clone()),
+            None => {
+                c.push_null();
+                Ok(())
+            }
+        });
+        self.rollback_on_error(len, pushed)
+    }
+
+    pub fn push_row_typed(&mut self, values: &HashMap<&str, CellValue>) {
+        if let Err(e) = self.try_push_row_typed(values) {
+            panic!("{}", e);
+        }
+    }
+
+    pub fn try_push_row(&mut self, values: &HashMap<&str, String>) -> Result<(), DmError> {
         // values: column_name -> value as str
-        // ensure all columns are present
-        for c in self.columns.iter_mut() {
-            let col_name = c.name();
-            if !values.contains_key(col_name) {
-                // Match c type and add default value
-                match c.get_type() {
-                    ColumnType::String => c.push_str(""),
-                    ColumnType::Int => c.push_str("0"),
-                    ColumnType::Float => c.push_str("0.0"),
-                }
-            } else {
-                c.push_str(values.get(col_name).unwrap());
+        // columns missing from `values` receive a null
+        let len = self.len();
+        let pushed = self.columns.iter_mut().try_for_each(|c| match values.get(c.name()) {
+            Some(val) => c.try_push_str(val),
+            None => {
+                c.push_null();
+                Ok(())
             }
+        });
+        self.rollback_on_error(len, pushed)
+    }
+
+    pub fn push_row(&mut self, values: &HashMap<&str, String>) {
+        if let Err(e) = self.try_push_row(values) {
+            panic!("{}", e);
         }
     }
 
-    pub fn create_index(&self, column: &str) -> HashMap<String, Vec<usize>> {
-        let idx = self.get_column_index(column).expect("Index column not found");
-        let mut map: HashMap<String, Vec<usize>> = HashMap::new();
+    // Keep the table rectangular when a row fails halfway through
+    fn rollback_on_error(&mut self, len: usize, pushed: Result<(), DmError>) -> Result<(), DmError> {
+        if pushed.is_err() {
+            for c in self.columns.iter_mut() {
+                c.truncate(len);
+            }
+        }
+        pushed
+    }
+
+    /// Whether the rows are in ascending order of the given columns, compared
+    /// left to right with nulls first.
+    pub fn is_sorted_by(&self, columns: &[&str]) -> Result<bool, DmError> {
+        let idxs = columns.iter().map(|&col| self.try_get_column_index(col)).collect::<Result<Vec<usize>, DmError>>()?;
+        Ok((1..self.len()).all(|i| {
+            idxs.iter()
+                .map(|&idx| self.columns[idx].get(i - 1).cmp(&self.columns[idx].get(i)))
+                .find(|o| o.is_ne())
+                .is_none_or(|o| o.is_lt())
+        }))
+    }
+
+    pub fn try_create_index(&self, column: &str) -> Result<HashMap<CellValue, Vec<usize>>, DmError> {
+        let idx = self.try_get_column_index(column)?;
+        let mut map: HashMap<CellValue, Vec<usize>> = HashMap::new();
         for i in 0..self.len() {
-            let key = self.columns[idx].get_as_string(i);
+            let key = self.columns[idx].get(i);
             map.entry(key).or_default().push(i);
         }
-        map
+        Ok(map)
     }
 
-    pub fn create_multi_index(&self, columns: &[&str]) -> HashMap<Vec<String>, Vec<usize>> {
-        let idxs: Vec<usize> = columns.iter().map(|&col| self.get_column_index(col).expect("Index column not found")).collect();
-        let mut map: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+    pub fn create_index(&self, column: &str) -> HashMap<CellValue, Vec<usize>> {
+        self.try_create_index(column).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_create_multi_index(&self, columns: &[&str]) -> Result<HashMap<Vec<CellValue>, Vec<usize>>, DmError> {
+        let idxs = columns.iter().map(|&col| self.try_get_column_index(col)).collect::<Result<Vec<usize>, DmError>>()?;
+        let mut map: HashMap<Vec<CellValue>, Vec<usize>> = HashMap::new();
         for i in 0..self.len() {
-            let key: Vec<String> = idxs.iter().map(|&idx| self.columns[idx].get_as_string(i)).collect();
+            let key: Vec<CellValue> = idxs.iter().map(|&idx| self.columns[idx].get(i)).collect();
             map.entry(key).or_default().push(i);
         }
-        map
+        Ok(map)
+    }
+
+    pub fn create_multi_index(&self, columns: &[&str]) -> HashMap<Vec<CellValue>, Vec<usize>> {
+        self.try_create_multi_index(columns).unwrap_or_else(|e| panic!("{}", e))
     }
 
 }
@@ -117,35 +221,120 @@ impl Debug for Table {
         let header = self.column_names();
         let rows = (0..self.len())
             .map(|i| {
-                header.iter()
-                    .map(|c| self.columns[self.get_column_index(c).unwrap()].get_as_string(i))
+                self.columns.iter()
+                    .map(|c| if c.is_null(i) { "null".to_string() } else { c.get_as_string(i) })
                     .collect::<Vec<String>>()
             })
             .collect::<Vec<Vec<String>>>();
 
-        // print header
-        table.push_str("|");
-        for h in header.as_slice() {
-            table.push_str(&format!(" {} |", h));
+        // print header, stacked one line per level when columns have multi-level headers
+        let levels: Vec<Vec<String>> = self.columns.iter()
+            .map(|c| c.header().map_or_else(|| vec![c.name().to_string()], ColumnHeader::levels))
+            .collect();
+        let depth = levels.iter().map(Vec::len).max().unwrap_or(1);
+        for line in 0..depth {
+            table.push('|');
+            for (i, col_levels) in levels.iter().enumerate() {
+                // plain column names sit on the last line
+                let pad = depth - col_levels.len();
+                let label = if line < pad { "" } else { col_levels[line - pad].as_str() };
+                // leave out a label repeated from the previous column under the same parents
+                let repeated = line + 1 < depth && i > 0 && line >= pad && {
+                    let prev = &levels[i - 1];
+                    let prev_pad = depth - prev.len();
+                    prev_pad == pad && prev[..=line - pad] == col_levels[..=line - pad]
+                };
+                table.push_str(&format!(" {} |", if repeated { "" } else { label }));
+            }
+            table.push('\n');
         }
-        table.push_str("\n");
 
         // print separator
-        table.push_str("|");
+        table.push('|');
         for _ in header.as_slice() {
             table.push_str(" --- |");
         }
-        table.push_str("\n");
+        table.push('\n');
 
         // print rows
         for row in rows {
-            table.push_str("|");
+            table.push('|');
             for cell in row {
                 table.push_str(&format!(" {} |", cell));
             }
-            table.push_str("\n");
+            table.push('\n');
         }
 
         write!(f, "{}", table)
     }
-}
\ No newline at end of file
+}
+// Fixtures shared by the unit tests of every module
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::*;
+
+    // A table from columns of (name, type, values)
+    pub fn table(columns: &[(&str, ColumnType, Vec<CellValue>)]) -> Table {
+        let mut t = Table::new();
+        for (name, col_type, values) in columns {
+            let mut col = col_type.new_column(name);
+            for v in values {
+                col.try_push(v.clone()).unwrap();
+            }
+            t.add_column(col);
+        }
+        t
+    }
+
+    // The values of a column, by name
+    pub fn values(t: &Table, name: &str) -> Vec<CellValue> {
+        let idx = t.try_get_column_index(name).unwrap();
+        (0..t.len()).map(|i| t.columns[idx].get(i)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::fixtures::{table, values};
+
+    fn people() -> Table {
+        table(&[
+            ("name", ColumnType::String, vec![CellValue::Text("ann".into())]),
+            ("age", ColumnType::Int, vec![CellValue::Int(30)]),
+        ])
+    }
+
+    #[test]
+    fn add_column_checks_name_and_length() {
+        let mut t = people();
+        assert!(matches!(t.try_add_typed_column("age", ColumnType::Float), Err(DmError::DuplicateColumn(name)) if name == "age"));
+        assert!(matches!(t.try_add_float_column("score"), Err(DmError::LengthMismatch { expected: 1, found: 0 })));
+        assert_eq!(t.column_names(), ["name", "age"]);
+        assert!(matches!(t.try_get_column_index("score"), Err(DmError::ColumnNotFound(_))));
+    }
+
+    #[test]
+    fn failed_row_is_rolled_back() {
+        let mut t = people();
+        let row = HashMap::from([("name", "bob".to_string()), ("age", "old".to_string())]);
+        assert!(matches!(t.try_push_row(&row), Err(DmError::ParseError { row: 1, .. })));
+        let row = HashMap::from([("name", CellValue::Text("bob".into())), ("age", CellValue::Float(1.5))]);
+        assert!(matches!(t.try_push_row_typed(&row), Err(DmError::TypeMismatch { .. })));
+        assert_eq!(t.len(), 1);
+        assert!(t.columns.iter().all(|c| c.len() == 1));
+    }
+
+    #[test]
+    fn missing_values_become_nulls() {
+        let mut t = people();
+        t.push_row(&HashMap::from([("name", "bob".to_string())]));
+        assert_eq!(values(&t, "age"), [CellValue::Int(30), CellValue::Null]);
+        // null cells are left out of the rows, so they push back unchanged
+        let rows = t.get_rows();
+        assert!(!rows[1].contains_key("age"));
+        let mut copy = table(&[("name", ColumnType::String, vec![]), ("age", ColumnType::Int, vec![])]);
+        rows.iter().for_each(|row| copy.push_row(row));
+        assert_eq!(copy.get_rows_typed(), t.get_rows_typed());
+    }
+}